@@ -0,0 +1,455 @@
+use bevy::prelude::*;
+
+use crate::{
+    debug::PhysicsDebug,
+    ghost::Ghosts,
+    health::{KillPlane, KillVolumeBehavior},
+    hud::HudSections,
+    net::LinkConditioner,
+    player::{CameraConfig, FpsController, LogicalPlayer, RenderPlayer},
+    preset::ActivePreset,
+    settings::Settings,
+    spawn::SpawnPolicy,
+};
+
+/// A named value the console can read and write somewhere in the world.
+#[derive(Clone, Copy)]
+pub struct Cvar {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// `None` when whatever holds the value doesn't exist right now
+    pub get: fn(&mut World) -> Option<String>,
+    pub set: fn(&mut World, &str) -> Result<(), String>,
+}
+
+/// Every cvar the console knows, sorted by name.
+#[derive(Resource)]
+pub struct Cvars {
+    pub cvars: Vec<Cvar>,
+}
+
+impl Cvars {
+    pub fn find(&self, name: &str) -> Option<Cvar> {
+        self.cvars.iter().find(|cvar| cvar.name == name).copied()
+    }
+}
+
+/// How a cvar's value is written in the console.
+pub trait CvarValue: Sized {
+    fn parse(value: &str) -> Result<Self, String>;
+    fn display(&self) -> String;
+}
+
+impl CvarValue for f32 {
+    fn parse(value: &str) -> Result<Self, String> {
+        value
+            .parse()
+            .map_err(|_| format!("Expected a number, got \"{value}\""))
+    }
+
+    fn display(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl CvarValue for bool {
+    fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "1" | "true" | "on" => Ok(true),
+            "0" | "false" | "off" => Ok(false),
+            _ => Err(format!("Expected 0 or 1, got \"{value}\"")),
+        }
+    }
+
+    fn display(&self) -> String {
+        String::from(if *self { "1" } else { "0" })
+    }
+}
+
+impl CvarValue for String {
+    fn parse(value: &str) -> Result<Self, String> {
+        Ok(value.to_string())
+    }
+
+    fn display(&self) -> String {
+        self.clone()
+    }
+}
+
+impl CvarValue for SpawnPolicy {
+    fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "first" => Ok(SpawnPolicy::First),
+            "random" => Ok(SpawnPolicy::Random),
+            "farthest" => Ok(SpawnPolicy::FarthestFromEnemies),
+            _ => Err(format!(
+                "Expected first, random or farthest, got \"{value}\""
+            )),
+        }
+    }
+
+    fn display(&self) -> String {
+        String::from(match self {
+            SpawnPolicy::First => "first",
+            SpawnPolicy::Random => "random",
+            SpawnPolicy::FarthestFromEnemies => "farthest",
+        })
+    }
+}
+
+impl CvarValue for KillVolumeBehavior {
+    fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "kill" => Ok(KillVolumeBehavior::Kill),
+            "respawn" => Ok(KillVolumeBehavior::Respawn),
+            "despawn" => Ok(KillVolumeBehavior::Despawn),
+            "reset" => Ok(KillVolumeBehavior::Reset),
+            _ => match value.parse() {
+                Ok(damage) => Ok(KillVolumeBehavior::DamagePerSecond(damage)),
+                Err(_) => Err(format!(
+                    "Expected kill, respawn, despawn, reset or a number, got \"{value}\""
+                )),
+            },
+        }
+    }
+
+    fn display(&self) -> String {
+        match self {
+            KillVolumeBehavior::Kill => String::from("kill"),
+            KillVolumeBehavior::DamagePerSecond(damage) => damage.to_string(),
+            KillVolumeBehavior::Respawn => String::from("respawn"),
+            KillVolumeBehavior::Despawn => String::from("despawn"),
+            KillVolumeBehavior::Reset => String::from("reset"),
+        }
+    }
+}
+
+/// A field of the local player's [`FpsController`].
+macro_rules! controller_cvar {
+    ($field:ident, $description:literal) => {
+        Cvar {
+            name: concat!("controller.", stringify!($field)),
+            description: $description,
+            get: |world| player_controller(world).map(|controller| controller.$field.display()),
+            set: |world, value| {
+                let value = CvarValue::parse(value)?;
+                tuning_unlocked(world)?;
+                let mut controller = player_controller(world).ok_or(NO_PLAYER)?;
+                controller.$field = value;
+                Ok(())
+            },
+        }
+    };
+}
+
+/// A field of a resource.
+macro_rules! resource_cvar {
+    ($name:literal, $resource:ty, $field:ident, $description:literal) => {
+        Cvar {
+            name: $name,
+            description: $description,
+            get: |world| {
+                world
+                    .get_resource::<$resource>()
+                    .map(|resource| resource.$field.display())
+            },
+            set: |world, value| {
+                let value = CvarValue::parse(value)?;
+                let mut resource = world
+                    .get_resource_mut::<$resource>()
+                    .ok_or(concat!(stringify!($resource), " isn't available"))?;
+                resource.$field = value;
+                Ok(())
+            },
+        }
+    };
+}
+
+const NO_PLAYER: &str = "There is no player";
+
+fn player_controller(world: &mut World) -> Option<Mut<'_, FpsController>> {
+    world
+        .query_filtered::<&mut FpsController, With<LogicalPlayer>>()
+        .get_single_mut(world)
+        .ok()
+}
+
+/// Refuses tuning changes while [`ActivePreset::locked`] says so.
+fn tuning_unlocked(world: &World) -> Result<(), String> {
+    match world
+        .get_resource::<ActivePreset>()
+        .and_then(|active| active.locked)
+    {
+        Some(reason) => Err(format!("Movement tuning can't be changed {reason}")),
+        None => Ok(()),
+    }
+}
+
+fn player_camera_config(world: &mut World) -> Option<Mut<'_, CameraConfig>> {
+    world
+        .query_filtered::<&mut CameraConfig, With<LogicalPlayer>>()
+        .get_single_mut(world)
+        .ok()
+}
+
+fn world_camera(world: &mut World) -> Option<(Mut<'_, Projection>, Mut<'_, FogSettings>)> {
+    world
+        .query_filtered::<(&mut Projection, &mut FogSettings), With<RenderPlayer>>()
+        .get_single_mut(world)
+        .ok()
+}
+
+/// Extinction and inscattering both fall off with the inverse of the visibility distance, so
+/// the current visibility is recovered from how they compare to the falloff at a distance of 1.
+fn fog_visibility(fog: &FogSettings) -> Option<f32> {
+    let FogFalloff::Atmospheric { extinction, .. } = fog.falloff else {
+        return None;
+    };
+    let FogFalloff::Atmospheric {
+        extinction: reference,
+        ..
+    } = crate::fog_falloff(1.0)
+    else {
+        return None;
+    };
+    Some(reference.max_element() / extinction.max_element())
+}
+
+impl Default for Cvars {
+    fn default() -> Self {
+        let mut cvars = vec![
+            controller_cvar!(gravity, "Downwards acceleration"),
+            controller_cvar!(walk_speed, "Top speed on the ground"),
+            controller_cvar!(forward_speed, "Wish speed forwards and backwards"),
+            controller_cvar!(side_speed, "Wish speed sideways"),
+            controller_cvar!(
+                air_speed_cap,
+                "Wish speed cap in the air, low for air strafing"
+            ),
+            controller_cvar!(air_acceleration, "Acceleration in the air"),
+            controller_cvar!(max_air_speed, "Top speed in the air"),
+            controller_cvar!(acceleration, "Acceleration on the ground"),
+            controller_cvar!(friction, "Deceleration on the ground"),
+            controller_cvar!(
+                traction_normal_cutoff,
+                "Lowest ground normal Y that can be walked on"
+            ),
+            controller_cvar!(friction_speed_cutoff, "Speed below which friction stops"),
+            controller_cvar!(jump_speed, "Upwards speed of a jump"),
+            controller_cvar!(crouched_speed, "Top speed while crouched"),
+            controller_cvar!(crouch_speed, "How fast crouching lowers the collider"),
+            controller_cvar!(uncrouch_speed, "How fast standing up raises the collider"),
+            controller_cvar!(upright_height, "Collider height when standing"),
+            controller_cvar!(crouch_height, "Collider height when crouched"),
+            controller_cvar!(stop_speed, "Friction acts as if at least this fast"),
+            controller_cvar!(step_offset, "Tallest step walked up without jumping"),
+            Cvar {
+                name: "controller.preset",
+                description: "Movement preset loaded from assets/presets/<name>.preset.ron",
+                get: |world| {
+                    world
+                        .get_resource::<ActivePreset>()
+                        .map(|active| active.name.display())
+                },
+                set: |world, value| {
+                    let value = CvarValue::parse(value)?;
+                    tuning_unlocked(world)?;
+                    let mut active = world
+                        .get_resource_mut::<ActivePreset>()
+                        .ok_or("ActivePreset isn't available")?;
+                    active.name = value;
+                    Ok(())
+                },
+            },
+            Cvar {
+                name: "camera.height_offset",
+                description: "Eye height relative to the top of the collider",
+                get: |world| {
+                    player_camera_config(world).map(|config| config.height_offset.display())
+                },
+                set: |world, value| {
+                    let value = CvarValue::parse(value)?;
+                    let mut config = player_camera_config(world).ok_or(NO_PLAYER)?;
+                    config.height_offset = value;
+                    Ok(())
+                },
+            },
+            resource_cvar!(
+                "camera.fov",
+                Settings,
+                fov,
+                "Vertical field of view in degrees"
+            ),
+            Cvar {
+                name: "fog.visibility",
+                description: "Distance objects fade out at",
+                get: |world| {
+                    fog_visibility(&world_camera(world)?.1).map(|visibility| visibility.display())
+                },
+                set: |world, value| {
+                    let value: f32 = CvarValue::parse(value)?;
+                    if value <= 0.0 {
+                        return Err(String::from("Visibility has to be positive"));
+                    }
+                    let (_, mut fog) = world_camera(world).ok_or("There is no camera")?;
+                    fog.falloff = crate::fog_falloff(value);
+                    Ok(())
+                },
+            },
+            resource_cvar!(
+                "postprocess.intensity",
+                Settings,
+                post_process_intensity,
+                "Strength of the pixelation pass"
+            ),
+            resource_cvar!(
+                "postprocess.block_size",
+                Settings,
+                pixelation,
+                "Size of the pixelation blocks in pixels"
+            ),
+            resource_cvar!(
+                "audio.volume",
+                Settings,
+                volume,
+                "Global volume from 0 to 1"
+            ),
+            resource_cvar!(
+                "mouse.sensitivity",
+                Settings,
+                sensitivity,
+                "Radians per pixel of mouse movement"
+            ),
+            resource_cvar!(
+                "mouse.invert_y",
+                Settings,
+                invert_y,
+                "Moving the mouse up looks down"
+            ),
+            resource_cvar!(
+                "debug.physics",
+                PhysicsDebug,
+                enabled,
+                "Draw colliders and controller casts"
+            ),
+            resource_cvar!(
+                "hud.speed",
+                HudSections,
+                speed,
+                "Show speed and vertical velocity"
+            ),
+            resource_cvar!(
+                "hud.ground",
+                HudSections,
+                ground,
+                "Show ground tick and traction"
+            ),
+            resource_cvar!(
+                "hud.crouch",
+                HudSections,
+                crouch,
+                "Show the collider height"
+            ),
+            resource_cvar!(
+                "hud.performance",
+                HudSections,
+                performance,
+                "Show FPS and frame time"
+            ),
+            resource_cvar!("hud.timer", HudSections, timer, "Show the speedrun timer"),
+            resource_cvar!(
+                "killplane.height",
+                KillPlane,
+                height,
+                "Height below which things are killed or put back"
+            ),
+            Cvar {
+                name: "killplane.behavior",
+                description: "What falling below the kill plane does to players: kill, respawn or damage per second",
+                get: |world| {
+                    world
+                        .get_resource::<KillPlane>()
+                        .map(|kill_plane| kill_plane.behavior.display())
+                },
+                set: |world, value| {
+                    let value = CvarValue::parse(value)?;
+                    if matches!(
+                        value,
+                        KillVolumeBehavior::Despawn | KillVolumeBehavior::Reset
+                    ) {
+                        return Err(String::from("Players can only be killed or respawned"));
+                    }
+                    let mut kill_plane = world
+                        .get_resource_mut::<KillPlane>()
+                        .ok_or("KillPlane isn't available")?;
+                    kill_plane.behavior = value;
+                    Ok(())
+                },
+            },
+            Cvar {
+                name: "spawn.policy",
+                description: "Which spawn point is used: first, random or farthest from enemies",
+                get: |world| {
+                    world
+                        .get_resource::<SpawnPolicy>()
+                        .map(|policy| policy.display())
+                },
+                set: |world, value| {
+                    let value = CvarValue::parse(value)?;
+                    world.insert_resource::<SpawnPolicy>(value);
+                    Ok(())
+                },
+            },
+            Cvar {
+                name: "ghost.race_personal_best",
+                description: "Show the personal best ghost while running",
+                get: |world| {
+                    world
+                        .get_resource::<Ghosts>()
+                        .map(|ghosts| ghosts.race_personal_best.display())
+                },
+                set: |world, value| {
+                    let value = CvarValue::parse(value)?;
+                    let mut ghosts = world
+                        .get_resource_mut::<Ghosts>()
+                        .ok_or("Ghosts aren't available")?;
+                    ghosts.race_personal_best = value;
+                    ghosts.dirty = true;
+                    Ok(())
+                },
+            },
+            resource_cvar!(
+                "net.latency",
+                LinkConditioner,
+                latency,
+                "Simulated seconds of delay per packet"
+            ),
+            resource_cvar!(
+                "net.jitter",
+                LinkConditioner,
+                jitter,
+                "Simulated random delay in seconds, either way"
+            ),
+            resource_cvar!(
+                "net.loss",
+                LinkConditioner,
+                loss,
+                "Simulated chance of losing a packet"
+            ),
+            resource_cvar!(
+                "net.duplication",
+                LinkConditioner,
+                duplication,
+                "Simulated chance of a packet arriving twice"
+            ),
+            resource_cvar!(
+                "net.reordering",
+                LinkConditioner,
+                reordering,
+                "Simulated chance of a packet arriving late"
+            ),
+        ];
+        cvars.sort_by_key(|cvar| cvar.name);
+        Self { cvars }
+    }
+}
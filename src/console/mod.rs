@@ -0,0 +1,404 @@
+mod cvars;
+
+use std::fs;
+
+use bevy::{
+    input::{
+        keyboard::{Key, KeyboardInput},
+        InputSystem,
+    },
+    prelude::*,
+    window::CursorGrabMode,
+};
+
+pub use cvars::{Cvar, CvarValue, Cvars};
+
+use crate::player::{fps_controller_input, FpsController, FpsControllerInput, LogicalPlayer};
+
+/// Lines of output kept in the console.
+const LOG_LENGTH: usize = 200;
+/// Lines of output shown above the input line.
+const VISIBLE_LINES: usize = 16;
+/// How deep `exec`ed files may `exec` other files, to stop files that exec themselves.
+const MAX_EXEC_DEPTH: usize = 8;
+
+const COMMANDS: [(&str, &str); 5] = [
+    ("clear", "Clears the console"),
+    (
+        "cvars",
+        "Lists cvars and their values, optionally only those starting with a prefix",
+    ),
+    ("echo", "Prints its arguments"),
+    ("exec", "Runs every line of a file as a command"),
+    ("help", "Lists commands, or describes a command or cvar"),
+];
+
+/// Drop down developer console, toggled with backquote. Typing a cvar's name shows its value,
+/// following it with a value sets it.
+pub struct ConsolePlugin;
+
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Console>()
+            .init_resource::<Cvars>()
+            .add_systems(Startup, setup_console)
+            .add_systems(
+                PreUpdate,
+                console_input
+                    .after(InputSystem)
+                    .before(fps_controller_input),
+            )
+            .add_systems(Update, (run_console_commands, update_console).chain());
+    }
+}
+
+#[derive(Resource)]
+pub struct Console {
+    pub open: bool,
+    pub toggle_key: KeyCode,
+    /// The line being typed
+    pub input: String,
+    pub log: Vec<String>,
+    /// Submitted lines, oldest first
+    pub history: Vec<String>,
+    /// Position in [`Console::history`] while browsing it with the arrow keys
+    pub history_index: Option<usize>,
+    /// Lines waiting to be run with world access
+    pub pending: Vec<String>,
+}
+
+impl Default for Console {
+    fn default() -> Self {
+        Self {
+            open: false,
+            toggle_key: KeyCode::Backquote,
+            input: String::new(),
+            log: Vec::new(),
+            history: Vec::new(),
+            history_index: None,
+            pending: Vec::new(),
+        }
+    }
+}
+
+impl Console {
+    pub fn print(&mut self, line: impl Into<String>) {
+        self.log.push(line.into());
+        if self.log.len() > LOG_LENGTH {
+            self.log.drain(..self.log.len() - LOG_LENGTH);
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct ConsoleRoot;
+
+#[derive(Component)]
+pub struct ConsoleText;
+
+pub fn setup_console(mut commands: Commands, assets: Res<AssetServer>) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(0.0),
+                    left: Val::Px(0.0),
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(40.0),
+                    padding: UiRect::all(Val::Px(8.0)),
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::FlexEnd,
+                    display: Display::None,
+                    ..default()
+                },
+                background_color: Color::srgba(0.0, 0.0, 0.0, 0.8).into(),
+                z_index: ZIndex::Global(10),
+                ..default()
+            },
+            ConsoleRoot,
+        ))
+        .with_children(|root| {
+            root.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font: assets.load("font.ttf"),
+                        font_size: 18.0,
+                        color: Color::WHITE,
+                    },
+                ),
+                ConsoleText,
+            ));
+        });
+}
+
+/// Edits the input line while the console is open, and swallows keyboard and mouse input so
+/// nothing else reacts to what is typed.
+#[allow(clippy::too_many_arguments)]
+pub fn console_input(
+    mut console: ResMut<Console>,
+    cvars: Res<Cvars>,
+    mut keyboard_events: EventReader<KeyboardInput>,
+    mut key_input: ResMut<ButtonInput<KeyCode>>,
+    mut mouse_input: ResMut<ButtonInput<MouseButton>>,
+    mut window_query: Query<&mut Window>,
+    mut player_query: Query<(&mut FpsController, &mut FpsControllerInput), With<LogicalPlayer>>,
+) {
+    if key_input.just_pressed(console.toggle_key) {
+        console.open = !console.open;
+        key_input.reset(console.toggle_key);
+        if console.open {
+            for mut window in &mut window_query {
+                window.cursor.grab_mode = CursorGrabMode::None;
+                window.cursor.visible = true;
+            }
+            for (mut controller, mut input) in &mut player_query {
+                controller.enable_input = false;
+                input.movement = Vec3::ZERO;
+                input.jump = false;
+                input.crouch = false;
+            }
+        }
+        // The key that opened the console shouldn't be typed into it
+        keyboard_events.clear();
+    }
+    if !console.open {
+        keyboard_events.clear();
+        return;
+    }
+    let console = &mut *console;
+
+    for event in keyboard_events.read() {
+        if !event.state.is_pressed() {
+            continue;
+        }
+        match &event.logical_key {
+            Key::Enter => {
+                let line = std::mem::take(&mut console.input);
+                console.history_index = None;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                if console.history.last() != Some(&line) {
+                    console.history.push(line.clone());
+                }
+                console.print(format!("> {line}"));
+                console.pending.push(line);
+            }
+            Key::Backspace => {
+                console.input.pop();
+            }
+            Key::Tab => complete(console, &cvars),
+            Key::Escape => console.open = false,
+            Key::ArrowUp => {
+                let index = match console.history_index {
+                    Some(index) => index.saturating_sub(1),
+                    None => match console.history.len().checked_sub(1) {
+                        Some(index) => index,
+                        None => continue,
+                    },
+                };
+                console.history_index = Some(index);
+                console.input.clone_from(&console.history[index]);
+            }
+            Key::ArrowDown => {
+                let Some(index) = console.history_index else {
+                    continue;
+                };
+                if index + 1 < console.history.len() {
+                    console.history_index = Some(index + 1);
+                    console.input.clone_from(&console.history[index + 1]);
+                } else {
+                    console.history_index = None;
+                    console.input.clear();
+                }
+            }
+            Key::Space => console.input.push(' '),
+            Key::Character(text) => {
+                console
+                    .input
+                    .extend(text.chars().filter(|character| !character.is_control()));
+            }
+            _ => {}
+        }
+    }
+
+    key_input.reset_all();
+    mouse_input.reset_all();
+}
+
+/// Completes the first word to the longest prefix shared by every command and cvar it could
+/// be, listing them when there is more than one.
+fn complete(console: &mut Console, cvars: &Cvars) {
+    if console.input.contains(' ') {
+        return;
+    }
+    let names: Vec<&str> = COMMANDS
+        .iter()
+        .map(|(name, _)| *name)
+        .chain(cvars.cvars.iter().map(|cvar| cvar.name))
+        .filter(|name| name.starts_with(console.input.as_str()))
+        .collect();
+    let Some(&first) = names.first() else {
+        return;
+    };
+
+    let shared = names.iter().fold(first.len(), |length, name| {
+        first
+            .chars()
+            .zip(name.chars())
+            .take(length)
+            .take_while(|(a, b)| a == b)
+            .count()
+    });
+    if names.len() == 1 {
+        console.input = format!("{first} ");
+        return;
+    }
+    if shared == console.input.len() {
+        for name in names {
+            console.print(format!("  {name}"));
+        }
+    }
+    console.input = first[..shared].to_string();
+}
+
+/// Runs submitted lines, which can touch anything a cvar points at.
+pub fn run_console_commands(world: &mut World) {
+    let pending = std::mem::take(&mut world.resource_mut::<Console>().pending);
+    for line in pending {
+        execute(world, &line, 0);
+    }
+}
+
+/// Runs one line of console input, printing any output to the console.
+pub fn execute(world: &mut World, line: &str, depth: usize) {
+    let mut words = line.split_whitespace();
+    let Some(command) = words.next() else {
+        return;
+    };
+    let arguments: Vec<&str> = words.collect();
+
+    match command {
+        "clear" => world.resource_mut::<Console>().log.clear(),
+        "cvars" => {
+            let prefix = arguments.first().copied().unwrap_or_default();
+            let cvars: Vec<Cvar> = world
+                .resource::<Cvars>()
+                .cvars
+                .iter()
+                .filter(|cvar| cvar.name.starts_with(prefix))
+                .copied()
+                .collect();
+            for cvar in cvars {
+                let value = (cvar.get)(world).unwrap_or_else(|| String::from("-"));
+                world
+                    .resource_mut::<Console>()
+                    .print(format!("  {} {value}", cvar.name));
+            }
+        }
+        "echo" => world.resource_mut::<Console>().print(arguments.join(" ")),
+        "exec" => {
+            let Some(path) = arguments.first() else {
+                world.resource_mut::<Console>().print("Usage: exec <path>");
+                return;
+            };
+            if depth >= MAX_EXEC_DEPTH {
+                world
+                    .resource_mut::<Console>()
+                    .print(format!("Not running {path}, too many nested execs"));
+                return;
+            }
+            match fs::read_to_string(path) {
+                Ok(contents) => {
+                    for line in contents.lines() {
+                        let line = line.trim();
+                        if !line.starts_with("//") {
+                            execute(world, line, depth + 1);
+                        }
+                    }
+                }
+                Err(error) => world
+                    .resource_mut::<Console>()
+                    .print(format!("Couldn't read {path}: {error}")),
+            }
+        }
+        "help" => {
+            let output = match arguments.first() {
+                Some(name) => describe(world, name),
+                None => COMMANDS
+                    .iter()
+                    .map(|(name, description)| format!("  {name}: {description}"))
+                    .chain([String::from(
+                        "Type a cvar's name to see its value, or follow it with a value to set it",
+                    )])
+                    .collect(),
+            };
+            let mut console = world.resource_mut::<Console>();
+            for line in output {
+                console.print(line);
+            }
+        }
+        name => {
+            let Some(cvar) = world.resource::<Cvars>().find(name) else {
+                world
+                    .resource_mut::<Console>()
+                    .print(format!("Unknown command or cvar \"{name}\""));
+                return;
+            };
+            let output = match arguments.as_slice() {
+                [] => match (cvar.get)(world) {
+                    Some(value) => format!("{name} is {value}"),
+                    None => format!("{name} isn't available right now"),
+                },
+                [value] => match (cvar.set)(world, value) {
+                    Ok(()) => return,
+                    Err(error) => error,
+                },
+                _ => format!("Usage: {name} <value>"),
+            };
+            world.resource_mut::<Console>().print(output);
+        }
+    }
+}
+
+fn describe(world: &mut World, name: &str) -> Vec<String> {
+    if let Some((_, description)) = COMMANDS.iter().find(|(command, _)| *command == name) {
+        return vec![format!("{name}: {description}")];
+    }
+    let Some(cvar) = world.resource::<Cvars>().find(name) else {
+        return vec![format!("Unknown command or cvar \"{name}\"")];
+    };
+    let value = (cvar.get)(world).unwrap_or_else(|| String::from("-"));
+    vec![format!("{name} {value}: {}", cvar.description)]
+}
+
+pub fn update_console(
+    console: Res<Console>,
+    mut root_query: Query<&mut Style, With<ConsoleRoot>>,
+    mut text_query: Query<&mut Text, With<ConsoleText>>,
+) {
+    if !console.is_changed() {
+        return;
+    }
+    for mut style in &mut root_query {
+        style.display = if console.open {
+            Display::Flex
+        } else {
+            Display::None
+        };
+    }
+
+    let start = console.log.len().saturating_sub(VISIBLE_LINES);
+    let mut text = console.log[start..].join("\n");
+    if !text.is_empty() {
+        text.push('\n');
+    }
+    text.push_str("> ");
+    text.push_str(&console.input);
+    text.push('_');
+    for mut console_text in &mut text_query {
+        console_text.sections[0].value.clone_from(&text);
+    }
+}
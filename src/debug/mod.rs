@@ -0,0 +1,97 @@
+use bevy::{color::palettes::css, prelude::*};
+use bevy_rapier3d::prelude::*;
+
+use crate::player::{ControllerCast, ControllerDebug, FpsController, LogicalPlayer};
+
+pub struct PhysicsDebugPlugin;
+
+impl Plugin for PhysicsDebugPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(RapierDebugRenderPlugin::default().disabled())
+            .init_resource::<PhysicsDebug>()
+            .add_systems(
+                Update,
+                (
+                    toggle_physics_debug,
+                    sync_physics_debug,
+                    draw_controller_casts,
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// Draws every collider plus the player controller's ground, step and overhang casts.
+#[derive(Resource)]
+pub struct PhysicsDebug {
+    pub enabled: bool,
+    pub toggle_key: KeyCode,
+}
+
+impl Default for PhysicsDebug {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            toggle_key: KeyCode::F3,
+        }
+    }
+}
+
+pub fn toggle_physics_debug(key_input: Res<ButtonInput<KeyCode>>, mut debug: ResMut<PhysicsDebug>) {
+    if key_input.just_pressed(debug.toggle_key) {
+        debug.enabled = !debug.enabled;
+    }
+}
+
+/// Only records controller casts while the overlay is shown.
+pub fn sync_physics_debug(
+    mut commands: Commands,
+    debug: Res<PhysicsDebug>,
+    mut render_context: ResMut<DebugRenderContext>,
+    player_query: Query<(Entity, Has<ControllerDebug>), With<LogicalPlayer>>,
+) {
+    if render_context.enabled != debug.enabled {
+        render_context.enabled = debug.enabled;
+    }
+
+    for (entity, recording) in &player_query {
+        if debug.enabled && !recording {
+            commands.entity(entity).insert(ControllerDebug::default());
+        } else if !debug.enabled && recording {
+            commands.entity(entity).remove::<ControllerDebug>();
+        }
+    }
+}
+
+pub fn draw_controller_casts(mut gizmos: Gizmos, query: Query<(&FpsController, &ControllerDebug)>) {
+    for (controller, debug) in &query {
+        for (index, cast) in debug.casts.iter().enumerate() {
+            // The first cast is always the ground check
+            let hit_color = if index == 0 && !controller.has_traction {
+                css::ORANGE
+            } else {
+                css::LIME
+            };
+            draw_cast_shape(&mut gizmos, cast, cast.origin, css::DARK_GRAY);
+            gizmos.line(cast.origin, cast.end, css::DARK_GRAY);
+
+            let Some(hit) = &cast.hit else {
+                draw_cast_shape(&mut gizmos, cast, cast.end, css::RED);
+                continue;
+            };
+            draw_cast_shape(&mut gizmos, cast, hit.position, hit_color);
+            gizmos.sphere(hit.point, Quat::IDENTITY, 0.03, hit_color);
+            gizmos.arrow(hit.point, hit.point + hit.normal * 0.5, css::AQUA);
+        }
+    }
+}
+
+fn draw_cast_shape(gizmos: &mut Gizmos, cast: &ControllerCast, position: Vec3, color: Srgba) {
+    if let Some(cylinder) = cast.shape.as_cylinder() {
+        let cylinder = Cylinder::new(cylinder.radius(), cylinder.half_height() * 2.0);
+        gizmos.primitive_3d(&cylinder, position, cast.rotation, color);
+    } else if let Some(capsule) = cast.shape.as_capsule() {
+        let capsule = Capsule3d::new(capsule.radius(), capsule.half_height() * 2.0);
+        gizmos.primitive_3d(&capsule, position, cast.rotation, color);
+    }
+}
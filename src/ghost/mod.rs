@@ -0,0 +1,306 @@
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    player::{FpsController, LogicalPlayer},
+    preset::MovementPreset,
+    speedrun::{finish_run, PersonalBests, RunState, SpeedrunTimer},
+};
+
+/// Seconds between trajectory samples while a run is going.
+const SAMPLE_INTERVAL: f32 = 1.0 / 30.0;
+/// Height of the ghost mesh before it's scaled to the sampled crouch height.
+const GHOST_HEIGHT: f32 = 3.0;
+const GHOST_RADIUS: f32 = 0.5;
+
+pub struct GhostPlugin;
+
+impl Plugin for GhostPlugin {
+    fn build(&self, app: &mut App) {
+        let mut ghosts = Ghosts::default();
+        let mut args = std::env::args();
+        while let Some(arg) = args.next() {
+            if arg != "--ghost" {
+                continue;
+            }
+            let Some(path) = args.next() else {
+                break;
+            };
+            match GhostTrack::load(Path::new(&path)) {
+                Ok(track) => ghosts.extra.push(track),
+                Err(error) => warn!("Couldn't load ghost {path}: {error}"),
+            }
+        }
+
+        app.insert_resource(ghosts).add_systems(
+            Update,
+            (record_ghost, spawn_ghosts, move_ghosts)
+                .chain()
+                .after(finish_run),
+        );
+    }
+}
+
+/// Where a player was at some point of a run.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct GhostSample {
+    /// Run time in seconds
+    pub time: f32,
+    pub position: Vec3,
+    pub yaw: f32,
+    pub pitch: f32,
+    /// Collider height, shorter while crouched
+    pub height: f32,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct GhostTrack {
+    pub level: String,
+    pub samples: Vec<GhostSample>,
+    /// What the whole run was tuned with, `None` if it changed partway
+    pub tuning: Option<MovementPreset>,
+}
+
+impl GhostTrack {
+    /// Personal best ghosts are kept per level in the user's data directory.
+    pub fn personal_best_path(level: &str) -> Option<PathBuf> {
+        Some(
+            dirs::data_dir()?
+                .join("source")
+                .join("ghosts")
+                .join(format!("{level}.ghost")),
+        )
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, bincode::serialize(self)?)?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        Ok(bincode::deserialize(&fs::read(path)?)?)
+    }
+
+    /// Interpolated pose at `time`, `None` before the first or after the last sample.
+    pub fn sample_at(&self, time: f32) -> Option<GhostSample> {
+        let next = self.samples.partition_point(|sample| sample.time <= time);
+        let previous = self.samples.get(next.checked_sub(1)?)?;
+        let Some(next) = self.samples.get(next) else {
+            return (time <= previous.time).then_some(*previous);
+        };
+
+        let fraction = (time - previous.time) / (next.time - previous.time);
+        let yaw =
+            Quat::from_rotation_y(previous.yaw).slerp(Quat::from_rotation_y(next.yaw), fraction);
+        Some(GhostSample {
+            time,
+            position: previous.position.lerp(next.position, fraction),
+            yaw: yaw.to_euler(EulerRot::YXZ).0,
+            pitch: previous.pitch.lerp(next.pitch, fraction),
+            height: previous.height.lerp(next.height, fraction),
+        })
+    }
+}
+
+#[derive(Resource)]
+pub struct Ghosts {
+    /// Show the fastest run on the current level while running it
+    pub race_personal_best: bool,
+    /// Tracks passed with `--ghost <path>`, shown on the level they were recorded on
+    pub extra: Vec<GhostTrack>,
+    /// The run in progress
+    pub current: GhostTrack,
+    /// Ghosts need respawning, because the level, its personal best or the tuning changed
+    pub dirty: bool,
+}
+
+impl Default for Ghosts {
+    fn default() -> Self {
+        Self {
+            race_personal_best: true,
+            extra: Vec::new(),
+            current: GhostTrack::default(),
+            dirty: true,
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct Ghost {
+    pub track: GhostTrack,
+}
+
+/// Child of a [`Ghost`] pointing where it looks.
+#[derive(Component)]
+pub struct GhostVisor;
+
+pub fn record_ghost(
+    timer: Res<SpeedrunTimer>,
+    personal_bests: Res<PersonalBests>,
+    mut ghosts: ResMut<Ghosts>,
+    mut last_state: Local<RunState>,
+    mut last_tuning: Local<Option<MovementPreset>>,
+    player_query: Query<(&FpsController, &Transform), With<LogicalPlayer>>,
+) {
+    let state_changed = *last_state != timer.state;
+    *last_state = timer.state;
+    if ghosts.current.level != timer.level {
+        ghosts.current.level.clone_from(&timer.level);
+        ghosts.dirty = true;
+    }
+    let tuning = player_query
+        .get_single()
+        .ok()
+        .map(|(controller, _)| MovementPreset::capture(controller));
+    if tuning != *last_tuning {
+        *last_tuning = tuning;
+        ghosts.dirty = true;
+    }
+
+    match timer.state {
+        RunState::Idle => ghosts.current.samples.clear(),
+        RunState::Running => {
+            // Runs restart by going back through the start volume
+            if state_changed || timer.elapsed < SAMPLE_INTERVAL {
+                ghosts.current.samples.clear();
+                ghosts.current.tuning = tuning;
+            }
+            if ghosts.current.tuning != tuning {
+                ghosts.current.tuning = None;
+            }
+            let due = match ghosts.current.samples.last() {
+                Some(last) => timer.elapsed - last.time >= SAMPLE_INTERVAL,
+                None => true,
+            };
+            if let (true, Ok((controller, transform))) = (due, player_query.get_single()) {
+                ghosts.current.samples.push(GhostSample {
+                    time: timer.elapsed,
+                    position: transform.translation,
+                    yaw: controller.yaw,
+                    pitch: controller.pitch,
+                    height: controller.height,
+                });
+            }
+        }
+        RunState::Finished if state_changed => {
+            let is_best = personal_bests
+                .levels
+                .get(&timer.level)
+                .is_some_and(|best| best.time == timer.elapsed);
+            let path = GhostTrack::personal_best_path(&timer.level);
+            if let (true, Some(path)) = (is_best, path) {
+                if let Err(error) = ghosts.current.save(&path) {
+                    warn!("Couldn't save ghost to {}: {error}", path.display());
+                }
+                ghosts.dirty = true;
+            }
+        }
+        RunState::Finished => {}
+    }
+}
+
+pub fn spawn_ghosts(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut ghosts: ResMut<Ghosts>,
+    ghost_query: Query<Entity, With<Ghost>>,
+    player_query: Query<&FpsController, With<LogicalPlayer>>,
+) {
+    if !ghosts.dirty {
+        return;
+    }
+    ghosts.dirty = false;
+    for entity in &ghost_query {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    let level = ghosts.current.level.clone();
+    let personal_best = ghosts
+        .race_personal_best
+        .then(|| GhostTrack::personal_best_path(&level))
+        .flatten()
+        .and_then(|path| GhostTrack::load(&path).ok())
+        // Racing a run made with other tuning isn't a race
+        .filter(|track| {
+            let tuning = player_query.get_single().ok().map(MovementPreset::capture);
+            track.tuning.is_some() && track.tuning == tuning
+        });
+    let extra = ghosts.extra.iter().filter(|track| track.level == level);
+
+    let body = meshes.add(Capsule3d::new(
+        GHOST_RADIUS,
+        GHOST_HEIGHT - GHOST_RADIUS * 2.0,
+    ));
+    let visor = meshes.add(Cuboid::new(0.6, 0.15, 0.2));
+    let tracks = personal_best
+        .into_iter()
+        .map(|track| (track, Color::srgba(1.0, 0.8, 0.3, 0.35)))
+        .chain(extra.map(|track| (track.clone(), Color::srgba(0.5, 0.8, 1.0, 0.35))));
+    for (track, color) in tracks {
+        let material = materials.add(StandardMaterial {
+            base_color: color,
+            alpha_mode: AlphaMode::Blend,
+            unlit: true,
+            ..default()
+        });
+        commands
+            .spawn((
+                PbrBundle {
+                    mesh: body.clone(),
+                    material: material.clone(),
+                    visibility: Visibility::Hidden,
+                    ..default()
+                },
+                Ghost { track },
+            ))
+            .with_children(|ghost| {
+                ghost.spawn((
+                    PbrBundle {
+                        mesh: visor.clone(),
+                        material,
+                        transform: Transform::from_xyz(0.0, GHOST_HEIGHT * 0.3, -GHOST_RADIUS),
+                        ..default()
+                    },
+                    GhostVisor,
+                ));
+            });
+    }
+}
+
+/// Ghosts show where their run was at the current run time, and only while running.
+pub fn move_ghosts(
+    timer: Res<SpeedrunTimer>,
+    mut ghost_query: Query<(&Ghost, &mut Transform, &mut Visibility, &Children)>,
+    mut visor_query: Query<&mut Transform, (With<GhostVisor>, Without<Ghost>)>,
+) {
+    for (ghost, mut transform, mut visibility, children) in &mut ghost_query {
+        let sample = match timer.state {
+            RunState::Running => ghost.track.sample_at(timer.elapsed),
+            RunState::Idle | RunState::Finished => None,
+        };
+        let Some(sample) = sample else {
+            *visibility = Visibility::Hidden;
+            continue;
+        };
+
+        *visibility = Visibility::Inherited;
+        transform.translation = sample.position;
+        transform.rotation = Quat::from_rotation_y(sample.yaw);
+        transform.scale = Vec3::new(1.0, sample.height / GHOST_HEIGHT, 1.0);
+        for &child in children {
+            if let Ok(mut visor) = visor_query.get_mut(child) {
+                visor.rotation = Quat::from_rotation_x(sample.pitch);
+            }
+        }
+    }
+}
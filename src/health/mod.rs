@@ -0,0 +1,208 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::{
+    player::{FpsController, FpsControllerInput, LogicalPlayer},
+    spawn::RespawnPlayer,
+};
+
+pub struct HealthPlugin;
+
+impl Plugin for HealthPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<DamageEvent>()
+            .init_resource::<KillPlane>()
+            .init_resource::<RespawnDelay>()
+            .add_systems(
+                Update,
+                (record_original_transform, kill_plane, apply_damage).chain(),
+            );
+    }
+}
+
+#[derive(Component)]
+pub struct Health {
+    pub current: f32,
+    pub max: f32,
+}
+
+impl Health {
+    pub fn new(max: f32) -> Self {
+        Self { current: max, max }
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.current <= 0.0
+    }
+}
+
+#[derive(Event)]
+pub struct DamageEvent {
+    pub target: Entity,
+    pub amount: f32,
+}
+
+/// Present while an entity is dead, removed again when it respawns.
+#[derive(Component)]
+pub struct Dead {
+    pub respawn_timer: Timer,
+}
+
+/// Whatever falls below this height gets its [`KillVolumeBehavior`], or `behavior` if it has
+/// [`Health`] and no behavior of its own.
+#[derive(Resource)]
+pub struct KillPlane {
+    pub height: f32,
+    pub behavior: KillVolumeBehavior,
+}
+
+impl Default for KillPlane {
+    fn default() -> Self {
+        Self {
+            height: -50.0,
+            behavior: KillVolumeBehavior::Kill,
+        }
+    }
+}
+
+/// What happens to something once it falls below the [`KillPlane`].
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub enum KillVolumeBehavior {
+    /// Lethal damage
+    Kill,
+    /// Damage for every second spent below
+    DamagePerSecond(f32),
+    /// Back to the active checkpoint or spawn without dying, anything but the
+    /// [`LogicalPlayer`] is killed instead
+    Respawn,
+    Despawn,
+    /// Move back to where the body was when this component was added
+    Reset,
+}
+
+#[derive(Component)]
+pub struct OriginalTransform(pub Transform);
+
+/// Seconds between dying and respawning.
+#[derive(Resource)]
+pub struct RespawnDelay(pub f32);
+
+impl Default for RespawnDelay {
+    fn default() -> Self {
+        Self(2.0)
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub fn kill_plane(
+    mut commands: Commands,
+    time: Res<Time>,
+    kill_plane: Res<KillPlane>,
+    mut query: Query<
+        (
+            Entity,
+            Option<&KillVolumeBehavior>,
+            Has<Health>,
+            Has<LogicalPlayer>,
+            &mut Transform,
+            Option<&OriginalTransform>,
+            Option<&mut Velocity>,
+        ),
+        (Or<(With<Health>, With<KillVolumeBehavior>)>, Without<Dead>),
+    >,
+    mut damage_events: EventWriter<DamageEvent>,
+    mut respawn_events: EventWriter<RespawnPlayer>,
+) {
+    for (entity, behavior, has_health, is_player, mut transform, original, velocity) in &mut query {
+        if transform.translation.y > kill_plane.height {
+            continue;
+        }
+
+        let behavior = match behavior {
+            Some(behavior) => *behavior,
+            None if has_health => kill_plane.behavior,
+            None => continue,
+        };
+        match behavior {
+            KillVolumeBehavior::Respawn if is_player => {
+                respawn_events.send(RespawnPlayer);
+            }
+            KillVolumeBehavior::Kill | KillVolumeBehavior::Respawn => {
+                damage_events.send(DamageEvent {
+                    target: entity,
+                    amount: f32::INFINITY,
+                });
+            }
+            KillVolumeBehavior::DamagePerSecond(damage) => {
+                damage_events.send(DamageEvent {
+                    target: entity,
+                    amount: damage * time.delta_seconds(),
+                });
+            }
+            KillVolumeBehavior::Reset => {
+                let Some(original) = original else {
+                    continue;
+                };
+                *transform = original.0;
+                if let Some(mut velocity) = velocity {
+                    *velocity = Velocity::zero();
+                }
+            }
+            KillVolumeBehavior::Despawn => commands.entity(entity).despawn_recursive(),
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub fn apply_damage(
+    mut commands: Commands,
+    respawn_delay: Res<RespawnDelay>,
+    mut damage_events: EventReader<DamageEvent>,
+    mut query: Query<
+        (
+            &mut Health,
+            Option<&mut FpsController>,
+            Option<&mut FpsControllerInput>,
+        ),
+        Without<Dead>,
+    >,
+) {
+    for event in damage_events.read() {
+        let Ok((mut health, controller, input)) = query.get_mut(event.target) else {
+            continue;
+        };
+        // Several hits can land on the same frame, only the first lethal one counts
+        if health.is_dead() {
+            continue;
+        }
+
+        health.current = (health.current - event.amount).max(0.0);
+        if !health.is_dead() {
+            continue;
+        }
+
+        if let Some(mut controller) = controller {
+            controller.enable_input = false;
+        }
+        // Input stops being sampled once disabled, so drop whatever was held
+        if let Some(mut input) = input {
+            input.movement = Vec3::ZERO;
+            input.jump = false;
+            input.crouch = false;
+        }
+        commands.entity(event.target).insert(Dead {
+            respawn_timer: Timer::from_seconds(respawn_delay.0, TimerMode::Once),
+        });
+    }
+}
+
+pub fn record_original_transform(
+    mut commands: Commands,
+    query: Query<(Entity, &Transform), Added<KillVolumeBehavior>>,
+) {
+    for (entity, transform) in query.iter() {
+        commands
+            .entity(entity)
+            .insert(OriginalTransform(*transform));
+    }
+}
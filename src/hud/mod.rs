@@ -0,0 +1,146 @@
+use std::fmt::Write;
+
+use bevy::{
+    diagnostic::{DiagnosticsStore, FrameTimeDiagnosticsPlugin},
+    math::Vec3Swizzles,
+    prelude::*,
+};
+use bevy_rapier3d::prelude::*;
+
+use crate::{
+    player::{FpsController, LogicalPlayer},
+    speedrun::{format_run_time, PersonalBests, RunState, SpeedrunTimer},
+};
+
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<FrameTimeDiagnosticsPlugin>() {
+            app.add_plugins(FrameTimeDiagnosticsPlugin);
+        }
+        app.init_resource::<HudSections>()
+            .add_systems(Update, (toggle_hud, update_hud).chain());
+    }
+}
+
+/// The text the HUD writes its telemetry into.
+#[derive(Component)]
+pub struct HudText;
+
+/// Which parts of the telemetry are shown, the whole HUD is hidden when none are.
+#[derive(Resource)]
+pub struct HudSections {
+    /// Horizontal speed and vertical velocity
+    pub speed: bool,
+    /// Ground tick and traction
+    pub ground: bool,
+    pub crouch: bool,
+    /// FPS and frame time
+    pub performance: bool,
+    /// Speedrun time, personal best and delta
+    pub timer: bool,
+    pub toggle_key: KeyCode,
+}
+
+impl Default for HudSections {
+    fn default() -> Self {
+        Self {
+            speed: true,
+            ground: true,
+            crouch: true,
+            performance: true,
+            timer: true,
+            toggle_key: KeyCode::F4,
+        }
+    }
+}
+
+impl HudSections {
+    pub fn any(&self) -> bool {
+        self.speed || self.ground || self.crouch || self.performance || self.timer
+    }
+
+    pub fn set_all(&mut self, shown: bool) {
+        self.speed = shown;
+        self.ground = shown;
+        self.crouch = shown;
+        self.performance = shown;
+        self.timer = shown;
+    }
+}
+
+pub fn toggle_hud(key_input: Res<ButtonInput<KeyCode>>, mut sections: ResMut<HudSections>) {
+    if key_input.just_pressed(sections.toggle_key) {
+        let shown = !sections.any();
+        sections.set_all(shown);
+    }
+}
+
+pub fn update_hud(
+    sections: Res<HudSections>,
+    diagnostics: Res<DiagnosticsStore>,
+    timer: Res<SpeedrunTimer>,
+    personal_bests: Res<PersonalBests>,
+    player_query: Query<(&FpsController, &Velocity), With<LogicalPlayer>>,
+    mut hud_query: Query<&mut Text, With<HudText>>,
+) {
+    let mut hud = String::new();
+
+    if let Ok((controller, velocity)) = player_query.get_single() {
+        if sections.speed {
+            let horizontal = velocity.linvel.xz().length();
+            let _ = writeln!(hud, "Speed: {horizontal:.2}");
+            let _ = writeln!(hud, "Vertical: {:.2}", velocity.linvel.y);
+        }
+        if sections.ground {
+            let state = match (controller.ground_tick >= 1, controller.has_traction) {
+                (true, true) => "grounded",
+                (true, false) => "sliding",
+                (false, _) => "airborne",
+            };
+            let _ = writeln!(hud, "Ground tick: {}", controller.ground_tick);
+            let _ = writeln!(hud, "State: {state}");
+        }
+        if sections.crouch {
+            let _ = writeln!(
+                hud,
+                "Height: {:.2} / {:.2}",
+                controller.height, controller.upright_height
+            );
+        }
+    }
+
+    if sections.performance {
+        let fps = diagnostics
+            .get(&FrameTimeDiagnosticsPlugin::FPS)
+            .and_then(|fps| fps.smoothed());
+        let frame_time = diagnostics
+            .get(&FrameTimeDiagnosticsPlugin::FRAME_TIME)
+            .and_then(|frame_time| frame_time.smoothed());
+        if let (Some(fps), Some(frame_time)) = (fps, frame_time) {
+            let _ = writeln!(hud, "FPS: {fps:.0} ({frame_time:.2} ms)");
+        }
+    }
+
+    if sections.timer {
+        let best = personal_bests.levels.get(&timer.level);
+        if timer.state != RunState::Idle || best.is_some() {
+            let _ = write!(hud, "Time: {}", format_run_time(timer.elapsed));
+            if let Some(best) = best {
+                let _ = write!(hud, "  Best: {}", format_run_time(best.time));
+            }
+            if let Some(delta) = timer.delta() {
+                let sign = if delta < 0.0 { '-' } else { '+' };
+                let _ = write!(hud, "  ({sign}{})", format_run_time(delta.abs()));
+            }
+            hud.push('\n');
+        }
+    }
+
+    for mut text in &mut hud_query {
+        if text.sections[0].value != hud {
+            text.sections[0].value.clone_from(&hud);
+        }
+    }
+}
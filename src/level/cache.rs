@@ -0,0 +1,176 @@
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::{
+    asset::RecursiveDependencyLoadState,
+    gltf::{Gltf, GltfMesh, GltfNode},
+    prelude::*,
+};
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{asset_root, read_level, AssetRoot, LevelRegistry};
+
+/// Bump whenever the file layout or the way colliders are built from meshes changes,
+/// so stale caches get regenerated instead of loaded.
+const CACHE_VERSION: u32 = 1;
+
+/// Builds the colliders of every level in the [`LevelRegistry`], writes them next to each glTF
+/// and exits. Meant for a headless app, it doesn't need anything but the assets.
+pub struct BakeCollidersPlugin;
+
+impl Plugin for BakeCollidersPlugin {
+    fn build(&self, app: &mut App) {
+        let asset_root = asset_root(app);
+        app.insert_resource(LevelRegistry::load(&asset_root))
+            .insert_resource(AssetRoot(asset_root))
+            .add_systems(Startup, load_levels_to_bake)
+            .add_systems(Update, bake_loaded_levels);
+    }
+}
+
+/// Levels still loading, by asset path.
+#[derive(Resource)]
+pub struct PendingBakes {
+    pub levels: Vec<(String, Handle<Gltf>)>,
+    pub failed: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CachedCollider {
+    pub collider: Collider,
+    pub transform: Transform,
+    pub sensor: bool,
+}
+
+/// Collider shapes computed for a glTF, stored in `<path>.colliders` next to it.
+#[derive(Serialize, Deserialize)]
+pub struct ColliderCache {
+    pub version: u32,
+    /// Hash of the glTF file the colliders were built from
+    pub source_hash: u64,
+    pub colliders: Vec<CachedCollider>,
+}
+
+impl ColliderCache {
+    /// The cached colliders for the glTF at `asset_path`, if there are any and they were built
+    /// from the file as it is now.
+    pub fn load(asset_root: &Path, asset_path: &str) -> Option<Vec<CachedCollider>> {
+        let disk_path = asset_root.join(asset_path);
+        let source_hash = source_hash(&disk_path)?;
+        let bytes = fs::read(cache_path(&disk_path)).ok()?;
+        let cache: ColliderCache = match bincode::deserialize(&bytes) {
+            Ok(cache) => cache,
+            Err(error) => {
+                warn!("Ignoring unreadable collider cache for {asset_path}: {error}");
+                return None;
+            }
+        };
+        (cache.version == CACHE_VERSION && cache.source_hash == source_hash)
+            .then_some(cache.colliders)
+    }
+
+    pub fn save(
+        asset_root: &Path,
+        asset_path: &str,
+        colliders: Vec<CachedCollider>,
+    ) -> Result<PathBuf, Box<dyn Error>> {
+        let disk_path = asset_root.join(asset_path);
+        let source_hash = source_hash(&disk_path).ok_or("couldn't read the source glTF")?;
+        let cache = ColliderCache {
+            version: CACHE_VERSION,
+            source_hash,
+            colliders,
+        };
+        let path = cache_path(&disk_path);
+        fs::write(&path, bincode::serialize(&cache)?)?;
+        Ok(path)
+    }
+}
+
+fn cache_path(disk_path: &Path) -> PathBuf {
+    let mut path = disk_path.as_os_str().to_owned();
+    path.push(".colliders");
+    path.into()
+}
+
+/// FNV-1a over the file contents, stable across builds unlike `DefaultHasher`.
+fn source_hash(path: &Path) -> Option<u64> {
+    let bytes = fs::read(path).ok()?;
+    Some(bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    }))
+}
+
+pub fn load_levels_to_bake(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    registry: Res<LevelRegistry>,
+) {
+    let levels = registry
+        .levels
+        .iter()
+        .map(|level| (level.path.clone(), asset_server.load(level.path.clone())))
+        .collect();
+    commands.insert_resource(PendingBakes {
+        levels,
+        failed: false,
+    });
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn bake_loaded_levels(
+    asset_server: Res<AssetServer>,
+    asset_root: Res<AssetRoot>,
+    mut pending: ResMut<PendingBakes>,
+    gltf_assets: Res<Assets<Gltf>>,
+    gltf_node_assets: Res<Assets<GltfNode>>,
+    gltf_mesh_assets: Res<Assets<GltfMesh>>,
+    mesh_assets: Res<Assets<Mesh>>,
+    mut app_exit_events: EventWriter<AppExit>,
+) {
+    let pending = &mut *pending;
+    pending.levels.retain(|(path, handle)| {
+        match asset_server.recursive_dependency_load_state(handle) {
+            RecursiveDependencyLoadState::Loaded | RecursiveDependencyLoadState::Failed => {}
+            RecursiveDependencyLoadState::NotLoaded | RecursiveDependencyLoadState::Loading => {
+                return true;
+            }
+        }
+        let Some(gltf) = gltf_assets.get(handle) else {
+            error!("Couldn't load {path}");
+            pending.failed = true;
+            return false;
+        };
+
+        let contents = read_level(
+            gltf,
+            &gltf_node_assets,
+            &gltf_mesh_assets,
+            &mesh_assets,
+            true,
+        );
+        for error in &contents.errors {
+            warn!("{path}: {error}");
+        }
+        match ColliderCache::save(&asset_root.0, path, contents.colliders) {
+            Ok(cache_path) => info!("Baked colliders to {}", cache_path.display()),
+            Err(error) => {
+                error!("Couldn't bake colliders for {path}: {error}");
+                pending.failed = true;
+            }
+        }
+        false
+    });
+
+    if pending.levels.is_empty() {
+        app_exit_events.send(if pending.failed {
+            AppExit::error()
+        } else {
+            AppExit::Success
+        });
+    }
+}
@@ -0,0 +1,906 @@
+mod cache;
+
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::{
+    asset::{io::file::FileAssetReader, LoadState, RecursiveDependencyLoadState, UntypedAssetId},
+    gltf::{Gltf, GltfMesh, GltfNode},
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use bevy_rapier3d::prelude::*;
+use serde::Deserialize;
+
+pub use cache::BakeCollidersPlugin;
+use cache::{CachedCollider, ColliderCache};
+
+use crate::{
+    player::LogicalPlayer,
+    spawn::{spawn_point_from_node, RespawnPlayer, SpawnPoint, SpawnPoints},
+    trigger::{trigger_kind_from_node, trigger_volume_bundle, TriggerKind},
+};
+
+/// Where the [`LevelRegistry`] is read from, relative to the asset directory.
+const LEVELS_PATH: &str = "levels.ron";
+
+pub struct LevelPlugin;
+
+impl Plugin for LevelPlugin {
+    fn build(&self, app: &mut App) {
+        let asset_root = asset_root(app);
+        let registry = LevelRegistry::load(&asset_root);
+        app.add_event::<LoadLevel>()
+            .add_event::<SceneLoadError>()
+            .insert_resource(registry)
+            .insert_resource(AssetRoot(asset_root))
+            .init_resource::<MainScene>()
+            .init_resource::<RequiredLevel>()
+            .add_systems(Startup, spawn_loading_text)
+            .add_systems(
+                Update,
+                (
+                    switch_level_input,
+                    load_level,
+                    reload_changed_level,
+                    scene_colliders,
+                    update_loading_text,
+                )
+                    .chain(),
+            )
+            .add_systems(Update, (report_scene_load_errors, hide_scene_nodes));
+    }
+}
+
+/// Directory the [`AssetServer`] reads from on disk.
+#[derive(Resource)]
+pub struct AssetRoot(pub PathBuf);
+
+/// Directory the [`AssetServer`] reads from, as configured on the [`AssetPlugin`].
+pub fn asset_root(app: &App) -> PathBuf {
+    let file_path = app
+        .get_added_plugins::<AssetPlugin>()
+        .first()
+        .map_or("assets", |plugin| plugin.file_path.as_str());
+    FileAssetReader::get_base_path().join(file_path)
+}
+
+#[derive(Deserialize)]
+pub struct LevelInfo {
+    pub id: String,
+    pub name: String,
+    pub path: String,
+}
+
+/// Every map that can be loaded with [`LoadLevel`], in the order they are cycled through.
+/// Listed in `assets/levels.ron`, the first one is loaded on startup.
+#[derive(Resource)]
+pub struct LevelRegistry {
+    pub levels: Vec<LevelInfo>,
+}
+
+impl Default for LevelRegistry {
+    fn default() -> Self {
+        let mut registry = Self { levels: Vec::new() };
+        registry.register("playground", "Playground", "playground.glb");
+        registry
+    }
+}
+
+impl LevelRegistry {
+    /// Falls back to [`LevelRegistry::default`] when the list can't be read.
+    pub fn load(asset_root: &Path) -> Self {
+        let path = asset_root.join(LEVELS_PATH);
+        let levels = fs::read_to_string(&path)
+            .map_err(|error| error.to_string())
+            .and_then(|contents| ron::from_str(&contents).map_err(|error| error.to_string()));
+        match levels {
+            Ok(levels) => Self { levels },
+            Err(error) => {
+                warn!("Couldn't read {}: {error}", path.display());
+                Self::default()
+            }
+        }
+    }
+
+    pub fn register(&mut self, id: &str, name: &str, path: &str) {
+        self.levels.push(LevelInfo {
+            id: id.to_string(),
+            name: name.to_string(),
+            path: path.to_string(),
+        });
+    }
+
+    pub fn get(&self, id: &str) -> Option<&LevelInfo> {
+        self.levels.iter().find(|level| level.id == id)
+    }
+
+    /// The level after `id`, wrapping around.
+    pub fn next(&self, id: &str) -> Option<&LevelInfo> {
+        let index = self.levels.iter().position(|level| level.id == id);
+        let next = index.map_or(0, |index| (index + 1) % self.levels.len());
+        self.levels.get(next)
+    }
+}
+
+/// Unloads the current level, if any, and starts loading the one with this id.
+#[derive(Event)]
+pub struct LoadLevel {
+    pub id: String,
+}
+
+/// While set, the only level [`load_level`] loads, like the server's while connected.
+#[derive(Resource, Default)]
+pub struct RequiredLevel(pub Option<String>);
+
+/// Marks everything that belongs to the loaded level and goes away with it.
+#[derive(Component)]
+pub struct LevelEntity;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SceneStatus {
+    Unloaded,
+    Loading,
+    Loaded,
+    Failed,
+}
+
+#[derive(Resource)]
+pub struct MainScene {
+    pub level: String,
+    pub handle: Handle<Gltf>,
+    pub status: SceneStatus,
+    /// Fraction of the level's assets loaded so far
+    pub progress: f32,
+    /// Names of nodes that only exist for gameplay and shouldn't be drawn
+    pub hidden_nodes: HashSet<String>,
+    /// Where to put the player back once a hot reload finishes instead of respawning
+    pub kept_player_position: Option<Vec3>,
+}
+
+impl Default for MainScene {
+    fn default() -> Self {
+        Self {
+            level: String::new(),
+            handle: Handle::default(),
+            status: SceneStatus::Unloaded,
+            progress: 0.0,
+            hidden_nodes: HashSet::new(),
+            kept_player_position: None,
+        }
+    }
+}
+
+/// Something in the level couldn't be used. Anything short of the glTF itself failing
+/// only skips the affected part.
+#[derive(Event, Clone, Debug)]
+pub enum SceneLoadError {
+    AssetFailed(String),
+    DependencyFailed,
+    NoScene,
+    MissingNode { index: usize },
+    MissingMesh { node: String },
+    InvalidPrimitive { node: String, primitive: usize },
+}
+
+impl fmt::Display for SceneLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AssetFailed(error) => write!(f, "level failed to load: {error}"),
+            Self::DependencyFailed => write!(f, "some of the level's assets failed to load"),
+            Self::NoScene => write!(f, "level has no scene"),
+            Self::MissingNode { index } => write!(f, "node {index} isn't loaded"),
+            Self::MissingMesh { node } => write!(f, "mesh of node {node} isn't loaded"),
+            Self::InvalidPrimitive { node, primitive } => {
+                write!(
+                    f,
+                    "primitive {primitive} of node {node} can't be used as a collider"
+                )
+            }
+        }
+    }
+}
+
+/// Custom properties of a glTF node, `Null` when there are none or they aren't valid JSON.
+pub fn node_extras(node: &GltfNode) -> serde_json::Value {
+    node.extras
+        .as_ref()
+        .and_then(|extras| serde_json::from_str(&extras.value).ok())
+        .unwrap_or_default()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColliderShape {
+    TriMesh,
+    ConvexHull,
+    ConvexDecomposition,
+    /// Box fitted around the primitive's bounds
+    Box,
+    None,
+}
+
+/// How a mesh node takes part in physics, chosen by a `"collider"` extra
+/// (`trimesh`, `convex`, `decomposition`, `box`, `sensor` or `none`), a `"visible": false` extra
+/// or one of the name suffixes:
+///
+/// - `_col`: collision-only trimesh that isn't rendered
+/// - `_convex`: convex hull
+/// - `_decomp`: convex decomposition
+/// - `_box`: fitted box
+/// - `_sensor`: trimesh sensor
+/// - `_nocol`: rendered without a collider
+///
+/// Blender's `.001` style duplicate suffixes are ignored.
+#[derive(Clone, Copy, Debug)]
+pub struct NodeCollider {
+    pub shape: ColliderShape,
+    pub sensor: bool,
+    pub visible: bool,
+}
+
+impl NodeCollider {
+    pub fn from_node(node: &GltfNode) -> Self {
+        let mut node_collider = Self {
+            shape: ColliderShape::TriMesh,
+            sensor: false,
+            visible: true,
+        };
+
+        let name = node.name.to_lowercase();
+        let name = match name.rsplit_once('.') {
+            Some((base, suffix)) if suffix.chars().all(|c| c.is_ascii_digit()) => base,
+            _ => &name,
+        };
+        let tag = if name.ends_with("_nocol") {
+            "none"
+        } else if name.ends_with("_col") {
+            node_collider.visible = false;
+            "trimesh"
+        } else if name.ends_with("_convex") {
+            "convex"
+        } else if name.ends_with("_decomp") {
+            "decomposition"
+        } else if name.ends_with("_box") {
+            "box"
+        } else if name.ends_with("_sensor") {
+            "sensor"
+        } else {
+            "trimesh"
+        };
+
+        let extras = node_extras(node);
+        if let Some(visible) = extras.get("visible").and_then(|visible| visible.as_bool()) {
+            node_collider.visible = visible;
+        }
+        let tag = extras
+            .get("collider")
+            .and_then(|tag| tag.as_str())
+            .unwrap_or(tag);
+
+        node_collider.shape = match tag {
+            "convex" => ColliderShape::ConvexHull,
+            "decomposition" => ColliderShape::ConvexDecomposition,
+            "box" => ColliderShape::Box,
+            "none" => ColliderShape::None,
+            "sensor" => {
+                node_collider.sensor = true;
+                ColliderShape::TriMesh
+            }
+            _ => ColliderShape::TriMesh,
+        };
+        node_collider
+    }
+}
+
+/// Collider for one primitive and where it sits relative to its node.
+fn primitive_collider(mesh: &Mesh, shape: ColliderShape) -> Option<(Collider, Vec3)> {
+    let computed_shape = match shape {
+        ColliderShape::TriMesh => ComputedColliderShape::TriMesh,
+        ColliderShape::ConvexHull => ComputedColliderShape::ConvexHull,
+        ColliderShape::ConvexDecomposition => ComputedColliderShape::ConvexDecomposition(default()),
+        ColliderShape::Box => {
+            let aabb = mesh.compute_aabb()?;
+            let half_extents = Vec3::from(aabb.half_extents);
+            let collider = Collider::cuboid(half_extents.x, half_extents.y, half_extents.z);
+            return Some((collider, Vec3::from(aabb.center)));
+        }
+        ColliderShape::None => return None,
+    };
+    if !has_valid_triangles(mesh) {
+        return None;
+    }
+    Collider::from_bevy_mesh(mesh, &computed_shape).map(|collider| (collider, Vec3::ZERO))
+}
+
+/// Whether the primitive is whole triangles over its own vertices, parry panics otherwise.
+fn has_valid_triangles(mesh: &Mesh) -> bool {
+    let Some(vertex_count) = mesh
+        .attribute(Mesh::ATTRIBUTE_POSITION)
+        .map(|positions| positions.len())
+    else {
+        return false;
+    };
+    let Some(indices) = mesh.indices() else {
+        return false;
+    };
+    vertex_count > 0
+        && !indices.is_empty()
+        && indices.len() % 3 == 0
+        && indices.iter().all(|index| index < vertex_count)
+}
+
+/// World transform of every node by index. glTF node transforms are relative to their parent,
+/// and the scene is spawned at the origin, so accumulating down from the roots is enough.
+fn node_world_transforms(
+    gltf: &Gltf,
+    gltf_node_assets: &Assets<GltfNode>,
+) -> HashMap<usize, Transform> {
+    fn visit(
+        node: &GltfNode,
+        parent: GlobalTransform,
+        world_transforms: &mut HashMap<usize, Transform>,
+    ) {
+        let global = parent * GlobalTransform::from(node.transform);
+        world_transforms.insert(node.index, global.compute_transform());
+        for child in &node.children {
+            visit(child, global, world_transforms);
+        }
+    }
+
+    let nodes: Vec<&GltfNode> = gltf
+        .nodes
+        .iter()
+        .filter_map(|node| gltf_node_assets.get(node))
+        .collect();
+    let children: HashSet<usize> = nodes
+        .iter()
+        .flat_map(|node| node.children.iter().map(|child| child.index))
+        .collect();
+
+    let mut world_transforms = HashMap::new();
+    for root in nodes.iter().filter(|node| !children.contains(&node.index)) {
+        visit(root, GlobalTransform::IDENTITY, &mut world_transforms);
+    }
+    world_transforms
+}
+
+/// Center and half extents of everything a node's mesh covers, in the node's space.
+fn node_mesh_bounds(
+    node: &GltfNode,
+    gltf_mesh_assets: &Assets<GltfMesh>,
+    mesh_assets: &Assets<Mesh>,
+) -> Option<(Vec3, Vec3)> {
+    let gltf_mesh = gltf_mesh_assets.get(node.mesh.as_ref()?)?;
+    gltf_mesh
+        .primitives
+        .iter()
+        .filter_map(|primitive| mesh_assets.get(&primitive.mesh)?.compute_aabb())
+        .map(|aabb| (Vec3::from(aabb.min()), Vec3::from(aabb.max())))
+        .reduce(|(min_a, max_a), (min_b, max_b)| (min_a.min(min_b), max_a.max(max_b)))
+        .map(|(min, max)| ((min + max) * 0.5, (max - min) * 0.5))
+}
+
+pub fn switch_level_input(
+    key_input: Res<ButtonInput<KeyCode>>,
+    registry: Res<LevelRegistry>,
+    main_scene: Res<MainScene>,
+    mut load_level_events: EventWriter<LoadLevel>,
+) {
+    if !key_input.just_pressed(KeyCode::F6) || main_scene.status == SceneStatus::Loading {
+        return;
+    }
+    if let Some(next) = registry.next(&main_scene.level) {
+        load_level_events.send(LoadLevel {
+            id: next.id.clone(),
+        });
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn load_level(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    registry: Res<LevelRegistry>,
+    required: Res<RequiredLevel>,
+    mut main_scene: ResMut<MainScene>,
+    mut spawn_points: ResMut<SpawnPoints>,
+    mut load_level_events: EventReader<LoadLevel>,
+    level_entities: Query<Entity, With<LevelEntity>>,
+) {
+    let Some(event) = load_level_events.read().last() else {
+        return;
+    };
+    if let Some(required) = required
+        .0
+        .as_ref()
+        .filter(|required| **required != event.id)
+    {
+        warn!("Can't load {} right now, only {required}", event.id);
+        return;
+    }
+    let Some(level) = registry.get(&event.id) else {
+        warn!("No level named {}", event.id);
+        return;
+    };
+
+    unload_level(&mut commands, &level_entities, &mut spawn_points);
+    // Checkpoints still make sense when the same level is loaded again
+    if level.id != main_scene.level {
+        spawn_points.active = None;
+    }
+    *main_scene = MainScene {
+        level: level.id.clone(),
+        handle: asset_server.load(level.path.clone()),
+        status: SceneStatus::Loading,
+        ..default()
+    };
+}
+
+fn unload_level(
+    commands: &mut Commands,
+    level_entities: &Query<Entity, With<LevelEntity>>,
+    spawn_points: &mut SpawnPoints,
+) {
+    for entity in level_entities.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    spawn_points.points.clear();
+}
+
+/// Rebuilds the scene and its colliders when the level's glTF changes on disk,
+/// leaving the player where they were.
+pub fn reload_changed_level(
+    mut commands: Commands,
+    mut asset_events: EventReader<AssetEvent<Gltf>>,
+    mut main_scene: ResMut<MainScene>,
+    mut spawn_points: ResMut<SpawnPoints>,
+    level_entities: Query<Entity, With<LevelEntity>>,
+    player_query: Query<&Transform, With<LogicalPlayer>>,
+) {
+    let handle_id = main_scene.handle.id();
+    let mut modified = false;
+    for event in asset_events.read() {
+        modified |= matches!(event, AssetEvent::Modified { id } if *id == handle_id);
+    }
+    if !modified || main_scene.status == SceneStatus::Loading {
+        return;
+    }
+
+    info!("Reloading level {}", main_scene.level);
+    unload_level(&mut commands, &level_entities, &mut spawn_points);
+    main_scene.status = SceneStatus::Loading;
+    main_scene.progress = 0.0;
+    main_scene.hidden_nodes.clear();
+    main_scene.kept_player_position = player_query
+        .get_single()
+        .ok()
+        .map(|transform| transform.translation);
+}
+
+/// Share of the glTF's meshes and materials that are ready, the glTF itself counting for half.
+fn loading_progress(
+    asset_server: &AssetServer,
+    gltf_assets: &Assets<Gltf>,
+    handle: &Handle<Gltf>,
+) -> f32 {
+    let Some(gltf) = gltf_assets.get(handle) else {
+        return 0.0;
+    };
+
+    let ids: Vec<UntypedAssetId> = gltf
+        .meshes
+        .iter()
+        .map(|handle| handle.id().untyped())
+        .chain(gltf.materials.iter().map(|handle| handle.id().untyped()))
+        .collect();
+    if ids.is_empty() {
+        return 1.0;
+    }
+    let loaded = ids
+        .iter()
+        .filter(|&&id| asset_server.is_loaded_with_dependencies(id))
+        .count();
+    0.5 + 0.5 * loaded as f32 / ids.len() as f32
+}
+
+/// A trigger volume read from a node, see [`trigger_volume_bundle`].
+pub struct LevelTrigger {
+    pub kind: TriggerKind,
+    pub transform: Transform,
+    pub bounds: Option<(Vec3, Vec3)>,
+}
+
+/// Everything a level's glTF places besides its scene.
+pub struct LevelContents {
+    pub spawn_points: Vec<SpawnPoint>,
+    pub triggers: Vec<LevelTrigger>,
+    /// Names of nodes that only exist for gameplay and shouldn't be drawn
+    pub hidden_nodes: HashSet<String>,
+    /// Left empty unless asked to build them
+    pub colliders: Vec<CachedCollider>,
+    pub errors: Vec<SceneLoadError>,
+}
+
+/// Reads spawns, triggers and hidden nodes from a loaded glTF, and builds colliders from its
+/// meshes when `build_colliders` is set.
+fn read_level(
+    gltf: &Gltf,
+    gltf_node_assets: &Assets<GltfNode>,
+    gltf_mesh_assets: &Assets<GltfMesh>,
+    mesh_assets: &Assets<Mesh>,
+    build_colliders: bool,
+) -> LevelContents {
+    let mut contents = LevelContents {
+        spawn_points: Vec::new(),
+        triggers: Vec::new(),
+        hidden_nodes: HashSet::new(),
+        colliders: Vec::new(),
+        errors: Vec::new(),
+    };
+    let world_transforms = node_world_transforms(gltf, gltf_node_assets);
+    let find_node = |name: &str| {
+        let node = gltf_node_assets.get(gltf.named_nodes.get(name)?)?;
+        world_transforms.get(&node.index).copied()
+    };
+    for (index, node) in gltf.nodes.iter().enumerate() {
+        let Some(node) = gltf_node_assets.get(node) else {
+            contents.errors.push(SceneLoadError::MissingNode { index });
+            continue;
+        };
+        let Some(&transform) = world_transforms.get(&node.index) else {
+            continue;
+        };
+        if let Some(spawn_point) = spawn_point_from_node(node, transform) {
+            contents.spawn_points.push(spawn_point);
+        }
+
+        let bounds = node_mesh_bounds(node, gltf_mesh_assets, mesh_assets);
+        if let Some(kind) = trigger_kind_from_node(node, transform, bounds, find_node) {
+            contents.triggers.push(LevelTrigger {
+                kind,
+                transform,
+                bounds,
+            });
+            contents.hidden_nodes.insert(node.name.clone());
+            continue;
+        }
+
+        let Some(gltf_mesh) = &node.mesh else {
+            continue;
+        };
+        let Some(gltf_mesh) = gltf_mesh_assets.get(gltf_mesh) else {
+            contents.errors.push(SceneLoadError::MissingMesh {
+                node: node.name.clone(),
+            });
+            continue;
+        };
+
+        let node_collider = NodeCollider::from_node(node);
+        if !node_collider.visible {
+            contents.hidden_nodes.insert(node.name.clone());
+        }
+        if node_collider.shape == ColliderShape::None || !build_colliders {
+            continue;
+        }
+        for (primitive, mesh_primitive) in gltf_mesh.primitives.iter().enumerate() {
+            let collider = mesh_assets
+                .get(&mesh_primitive.mesh)
+                .and_then(|mesh| primitive_collider(mesh, node_collider.shape));
+            let Some((collider, offset)) = collider else {
+                contents.errors.push(SceneLoadError::InvalidPrimitive {
+                    node: node.name.clone(),
+                    primitive,
+                });
+                continue;
+            };
+            contents.colliders.push(CachedCollider {
+                collider,
+                transform: transform * Transform::from_translation(offset),
+                sensor: node_collider.sensor,
+            });
+        }
+    }
+    contents
+}
+
+/// Colliders come from the level's baked cache when it matches the glTF on disk and are built
+/// from its meshes otherwise. Spawns, triggers and hidden nodes are always read from the glTF.
+#[allow(clippy::too_many_arguments)]
+pub fn scene_colliders(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    asset_root: Res<AssetRoot>,
+    registry: Res<LevelRegistry>,
+    mut main_scene: ResMut<MainScene>,
+    gltf_assets: Res<Assets<Gltf>>,
+    gltf_mesh_assets: Res<Assets<GltfMesh>>,
+    gltf_node_assets: Res<Assets<GltfNode>>,
+    mesh_assets: Res<Assets<Mesh>>,
+    mut spawn_points: ResMut<SpawnPoints>,
+    mut respawn_events: EventWriter<RespawnPlayer>,
+    mut errors: EventWriter<SceneLoadError>,
+    mut player_query: Query<(&mut Transform, &mut Velocity), With<LogicalPlayer>>,
+) {
+    if main_scene.status != SceneStatus::Loading {
+        return;
+    }
+    main_scene.progress = loading_progress(&asset_server, &gltf_assets, &main_scene.handle);
+
+    match asset_server.load_state(&main_scene.handle) {
+        LoadState::Loaded => {}
+        LoadState::Failed(error) => {
+            errors.send(SceneLoadError::AssetFailed(error.to_string()));
+            main_scene.status = SceneStatus::Failed;
+            return;
+        }
+        LoadState::NotLoaded | LoadState::Loading => return,
+    }
+    // Meshes finish loading after the glTF itself, wait for all of them before
+    // building anything so no primitive gets skipped just for being late
+    match asset_server.recursive_dependency_load_state(&main_scene.handle) {
+        RecursiveDependencyLoadState::Loaded => {}
+        RecursiveDependencyLoadState::Failed => {
+            errors.send(SceneLoadError::DependencyFailed);
+        }
+        RecursiveDependencyLoadState::NotLoaded | RecursiveDependencyLoadState::Loading => return,
+    }
+
+    let Some(gltf) = gltf_assets.get(&main_scene.handle) else {
+        return;
+    };
+    let Some(scene) = gltf
+        .default_scene
+        .clone()
+        .or_else(|| gltf.scenes.first().cloned())
+    else {
+        errors.send(SceneLoadError::NoScene);
+        main_scene.status = SceneStatus::Failed;
+        return;
+    };
+
+    commands.spawn((SceneBundle { scene, ..default() }, LevelEntity));
+    let cached = registry
+        .get(&main_scene.level)
+        .and_then(|level| ColliderCache::load(&asset_root.0, &level.path));
+    let contents = read_level(
+        gltf,
+        &gltf_node_assets,
+        &gltf_mesh_assets,
+        &mesh_assets,
+        cached.is_none(),
+    );
+    errors.send_batch(contents.errors);
+    spawn_points.points.extend(contents.spawn_points);
+    for trigger in contents.triggers {
+        commands.spawn((
+            trigger_volume_bundle(trigger.kind, trigger.transform, trigger.bounds),
+            LevelEntity,
+        ));
+    }
+    main_scene.hidden_nodes.extend(contents.hidden_nodes);
+
+    for cached in cached.unwrap_or(contents.colliders) {
+        let mut entity = commands.spawn((
+            cached.collider,
+            RigidBody::Fixed,
+            TransformBundle::from_transform(cached.transform),
+            LevelEntity,
+        ));
+        if cached.sensor {
+            entity.insert(Sensor);
+        }
+    }
+
+    if let Some(position) = main_scene.kept_player_position.take() {
+        for (mut transform, mut velocity) in &mut player_query {
+            transform.translation = position;
+            velocity.linvel = Vec3::ZERO;
+        }
+    } else {
+        // Whatever the player was standing on is gone, start over on the new level's spawn
+        respawn_events.send(RespawnPlayer);
+    }
+    main_scene.status = SceneStatus::Loaded;
+    main_scene.progress = 1.0;
+}
+
+pub fn report_scene_load_errors(mut errors: EventReader<SceneLoadError>) {
+    for error in errors.read() {
+        warn!("{error}");
+    }
+}
+
+pub fn hide_scene_nodes(
+    main_scene: Res<MainScene>,
+    mut query: Query<(&Name, &mut Visibility), Added<Name>>,
+) {
+    for (name, mut visibility) in &mut query {
+        if main_scene.hidden_nodes.contains(name.as_str()) {
+            *visibility = Visibility::Hidden;
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct LoadingText;
+
+pub fn spawn_loading_text(mut commands: Commands, assets: Res<AssetServer>) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font: assets.load("font.ttf"),
+                font_size: 32.0,
+                color: Color::WHITE,
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(5.0),
+            right: Val::Px(5.0),
+            ..default()
+        }),
+        LoadingText,
+    ));
+}
+
+pub fn update_loading_text(
+    registry: Res<LevelRegistry>,
+    main_scene: Res<MainScene>,
+    mut query: Query<(&mut Text, &mut Visibility), With<LoadingText>>,
+) {
+    if !main_scene.is_changed() {
+        return;
+    }
+
+    for (mut text, mut visibility) in &mut query {
+        if main_scene.status != SceneStatus::Loading {
+            *visibility = Visibility::Hidden;
+            continue;
+        }
+
+        let name = registry
+            .get(&main_scene.level)
+            .map_or(main_scene.level.as_str(), |level| level.name.as_str());
+        text.sections[0].value = format!("Loading {name}... {:.0}%", main_scene.progress * 100.0);
+        *visibility = Visibility::Inherited;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{f32::consts::FRAC_PI_2, thread, time::Duration};
+
+    use bevy::{gltf::GltfPlugin, scene::ScenePlugin};
+
+    use super::*;
+
+    fn load_fixture(app: &mut App, path: &str) -> Handle<Gltf> {
+        let handle = app.world().resource::<AssetServer>().load(path.to_string());
+        for _ in 0..1000 {
+            app.update();
+            let asset_server = app.world().resource::<AssetServer>();
+            if asset_server.is_loaded_with_dependencies(&handle) {
+                return handle;
+            }
+            if let Some(LoadState::Failed(error)) = asset_server.get_load_state(&handle) {
+                panic!("{path} failed to load: {error}");
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        panic!("{path} didn't load in time");
+    }
+
+    fn assert_transform(name: &str, actual: Transform, expected: Transform) {
+        assert!(
+            actual.translation.abs_diff_eq(expected.translation, 1e-5)
+                && actual.rotation.angle_between(expected.rotation) < 1e-3
+                && actual.scale.abs_diff_eq(expected.scale, 1e-5),
+            "{name} is at {actual:?}, expected {expected:?}"
+        );
+    }
+
+    fn fixture_app() -> App {
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            AssetPlugin {
+                file_path: String::from("tests/fixtures"),
+                watch_for_changes_override: Some(false),
+                ..default()
+            },
+            ScenePlugin,
+            GltfPlugin::default(),
+        ))
+        // Normally registered by the render and PBR plugins
+        .init_asset::<Mesh>()
+        .init_asset::<StandardMaterial>();
+        app.finish();
+        app.cleanup();
+        app
+    }
+
+    #[test]
+    fn nested_nodes_accumulate_parent_transforms() {
+        let mut app = fixture_app();
+        let handle = load_fixture(&mut app, "nested.gltf");
+        let gltf = app.world().resource::<Assets<Gltf>>().get(&handle).unwrap();
+        let gltf_node_assets = app.world().resource::<Assets<GltfNode>>();
+        let world_transforms = node_world_transforms(gltf, gltf_node_assets);
+        let world_transform = |name: &str| {
+            let node = gltf_node_assets.get(&gltf.named_nodes[name]).unwrap();
+            world_transforms[&node.index]
+        };
+
+        // The parent turns +X into -Z and +Z into +X, and doubles everything below it
+        let rotation = Quat::from_rotation_y(FRAC_PI_2);
+        assert_transform(
+            "Parent",
+            world_transform("Parent"),
+            Transform {
+                translation: Vec3::new(2.0, 1.0, 0.0),
+                rotation,
+                scale: Vec3::splat(2.0),
+            },
+        );
+        assert_transform(
+            "Child",
+            world_transform("Child"),
+            Transform {
+                translation: Vec3::new(2.0, 1.0, -2.0),
+                rotation,
+                scale: Vec3::splat(2.0),
+            },
+        );
+        assert_transform(
+            "Grandchild",
+            world_transform("Grandchild"),
+            Transform {
+                translation: Vec3::new(4.0, 1.0, -2.0),
+                rotation,
+                scale: Vec3::ONE,
+            },
+        );
+    }
+
+    #[test]
+    fn degenerate_primitives_are_reported_instead_of_panicking() {
+        let mut app = fixture_app();
+        let handle = load_fixture(&mut app, "degenerate.gltf");
+        let world = app.world();
+        let gltf = world.resource::<Assets<Gltf>>().get(&handle).unwrap();
+        let gltf_mesh_assets = world.resource::<Assets<GltfMesh>>();
+        let mesh_assets = world.resource::<Assets<Mesh>>();
+        let primitive_mesh = |name: &str, primitive: usize| {
+            let gltf_mesh = gltf_mesh_assets.get(&gltf.named_meshes[name]).unwrap();
+            mesh_assets
+                .get(&gltf_mesh.primitives[primitive].mesh)
+                .unwrap()
+        };
+
+        assert!(has_valid_triangles(primitive_mesh("Good", 0)));
+        // An index past the last vertex, and a triangle list that doesn't divide by three
+        assert!(!has_valid_triangles(primitive_mesh("Bad", 0)));
+        assert!(!has_valid_triangles(primitive_mesh("Bad", 1)));
+
+        let contents = read_level(
+            gltf,
+            world.resource::<Assets<GltfNode>>(),
+            gltf_mesh_assets,
+            mesh_assets,
+            true,
+        );
+        for primitive in 0..2 {
+            assert!(
+                contents.errors.iter().any(|error| matches!(
+                    error,
+                    SceneLoadError::InvalidPrimitive { node, primitive: index }
+                        if node == "Bad" && *index == primitive
+                )),
+                "primitive {primitive} of Bad wasn't reported"
+            );
+        }
+    }
+}
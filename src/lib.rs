@@ -0,0 +1,31 @@
+use bevy::prelude::*;
+
+pub mod console;
+pub mod debug;
+pub mod ghost;
+pub mod health;
+pub mod hud;
+pub mod level;
+pub mod menu;
+pub mod net;
+pub mod player;
+pub mod preset;
+pub mod processing;
+pub mod replay;
+pub mod settings;
+pub mod spawn;
+pub mod speedometer;
+pub mod speedrun;
+pub mod state;
+pub mod trigger;
+pub mod viewmodel;
+
+/// `visibility` is the distance in world units up to which objects retain visibility (>= 5%
+/// contrast).
+pub fn fog_falloff(visibility: f32) -> FogFalloff {
+    FogFalloff::from_visibility_colors(
+        visibility,
+        Color::srgb_u8(0x29, 0x27, 0x4c), // atmospheric extinction color (after light is lost due to absorption by atmospheric particles)
+        Color::srgb_u8(0x55, 0x5e, 0x88), // atmospheric inscattering color (light gained due to scattering from the sun)
+    )
+}
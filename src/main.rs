@@ -1,46 +1,123 @@
-use std::f32::consts::TAU;
-mod player;
-mod processing;
+use std::{f32::consts::TAU, time::Duration};
 
 use bevy::{
-    gltf::{Gltf, GltfMesh, GltfNode},
+    app::ScheduleRunnerPlugin,
     prelude::*,
-    render::camera::Exposure,
-    window::{CursorGrabMode, WindowResolution},
+    render::{
+        camera::{ClearColorConfig, Exposure},
+        settings::WgpuSettings,
+        RenderPlugin,
+    },
+    window::{CursorGrabMode, ExitCondition},
+    winit::WinitPlugin,
 };
 
 use bevy::core_pipeline::tonemapping::DebandDither;
 use bevy_rapier3d::prelude::*;
 
-use player::*;
-use processing::*;
+use source::console::*;
+use source::debug::*;
+use source::fog_falloff;
+use source::ghost::*;
+use source::health::*;
+use source::hud::*;
+use source::level::*;
+use source::menu::*;
+use source::net::*;
+use source::player::*;
+use source::preset::*;
+use source::processing::*;
+use source::replay::*;
+use source::settings::*;
+use source::spawn::*;
+use source::speedometer::*;
+use source::speedrun::*;
+use source::state::*;
+use source::trigger::*;
+use source::viewmodel::*;
 
-const SPAWN_POINT: Vec3 = Vec3::new(0.0, 1.625, 0.0);
+fn main() -> AppExit {
+    if std::env::args().any(|arg| arg == "--bake-colliders") {
+        return bake_colliders();
+    }
 
-fn main() {
     App::new()
         .insert_resource(AmbientLight {
             color: Color::srgb_u8(0xc9, 0xc7, 0xfc),
             brightness: 10000.0,
         })
         .insert_resource(ClearColor(Color::srgb_u8(0x19, 0x17, 0x3c)))
-        .add_plugins(DefaultPlugins.set(WindowPlugin {
-            primary_window: Some(Window {
-                resolution:
-                    WindowResolution::new(1920., 1080.).with_scale_factor_override(150.0 * 3.0),
-                ..default()
-            }),
-            ..default()
-        }))
+        .add_plugins(DefaultPlugins)
         .add_plugins(PostProcessPlugin)
-        .add_plugins(RapierPhysicsPlugin::<NoUserData>::default())
+        .add_plugins(RapierPhysicsPlugin::<NoUserData>::default().in_fixed_schedule())
         .add_plugins(FpsControllerPlugin)
-        .add_systems(Startup, setup)
-        .add_systems(Update, (manage_cursor, scene_colliders, respawn))
-        .run();
+        .add_plugins(MovementPresetPlugin)
+        .add_plugins(ViewModelPlugin)
+        .add_plugins(HealthPlugin)
+        .add_plugins(SpawnPlugin)
+        .add_plugins(TriggerPlugin)
+        .add_plugins(LevelPlugin)
+        .add_plugins(PhysicsDebugPlugin)
+        .add_plugins(HudPlugin)
+        .add_plugins(SpeedometerPlugin)
+        .add_plugins(SpeedrunPlugin)
+        .add_plugins(ReplayPlugin)
+        .add_plugins(GhostPlugin)
+        .add_plugins(NetPlugin)
+        .add_plugins(ConsolePlugin)
+        .add_plugins(SettingsPlugin)
+        .add_plugins(GameStatePlugin)
+        .add_plugins(PauseMenuPlugin)
+        .add_systems(Startup, (setup, configure_physics))
+        .add_systems(
+            Update,
+            (
+                manage_cursor.run_if(in_state(GameState::InGame)),
+                // Clients are respawned by the server
+                respawn.run_if(not(resource_exists::<NetClient>)),
+            ),
+        )
+        .run()
 }
 
-fn setup(mut commands: Commands, mut window: Query<&mut Window>, assets: Res<AssetServer>) {
+/// Writes the collider cache of every level without opening a window or touching the GPU.
+fn bake_colliders() -> AppExit {
+    App::new()
+        .add_plugins(
+            DefaultPlugins
+                .set(WindowPlugin {
+                    primary_window: None,
+                    exit_condition: ExitCondition::DontExit,
+                    close_when_requested: false,
+                })
+                .set(RenderPlugin {
+                    render_creation: WgpuSettings {
+                        backends: None,
+                        ..default()
+                    }
+                    .into(),
+                    ..default()
+                })
+                .disable::<WinitPlugin>(),
+        )
+        .add_plugins(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(
+            1.0 / 60.0,
+        )))
+        .add_plugins(BakeCollidersPlugin)
+        .run()
+}
+
+#[allow(clippy::too_many_arguments)]
+fn setup(
+    mut commands: Commands,
+    mut window: Query<&mut Window>,
+    assets: Res<AssetServer>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut load_level_events: EventWriter<LoadLevel>,
+    registry: Res<LevelRegistry>,
+    settings: Res<Settings>,
+) {
     let mut window = window.single_mut();
     window.title = String::from("im silly im silly im silly im silly");
 
@@ -55,75 +132,96 @@ fn setup(mut commands: Commands, mut window: Query<&mut Window>, assets: Res<Ass
     });
      */
 
-    let height = 3.0;
+    let controller = FpsController::default();
+    let height = controller.upright_height;
+    let spawn_point = SpawnPoint::default();
     let logical_entity = commands
         .spawn((
-            Collider::cylinder(height / 2.0, 0.5),
-            Friction {
-                coefficient: 0.0,
-                combine_rule: CoefficientCombineRule::Min,
-            },
-            Restitution {
-                coefficient: 0.0,
-                combine_rule: CoefficientCombineRule::Min,
-            },
-            ActiveEvents::COLLISION_EVENTS,
-            Velocity::zero(),
-            RigidBody::Dynamic,
-            Sleeping::disabled(),
-            LockedAxes::ROTATION_LOCKED,
-            AdditionalMassProperties::Mass(1.0),
-            GravityScale(0.0),
-            Ccd { enabled: true }, // Prevent clipping when going fast
-            TransformBundle::from_transform(Transform::from_translation(SPAWN_POINT)),
+            player_body(height, spawn_point.player_translation(height)),
             LogicalPlayer,
             FpsControllerInput {
-                pitch: -TAU / 12.0,
-                yaw: TAU * 5.0 / 8.0,
+                pitch: spawn_point.pitch,
+                yaw: spawn_point.yaw,
+                ..default()
+            },
+            controller,
+        ))
+        .insert((
+            CameraConfig {
+                height_offset: -0.5,
+            },
+            Health::new(100.0),
+        ))
+        .id();
+
+    let world_camera = commands
+        .spawn((
+            Camera3dBundle {
+                projection: Projection::Perspective(PerspectiveProjection {
+                    fov: settings.fov.to_radians(),
+                    ..default()
+                }),
+                deband_dither: DebandDither::Enabled,
+                exposure: Exposure::SUNLIGHT,
                 ..default()
             },
-            FpsController {
-                air_acceleration: 80.0,
+            FogSettings {
+                color: Color::BLACK,
+                falloff: fog_falloff(20.0),
                 ..default()
             },
+            RenderPlayer { logical_entity },
         ))
-        .insert(CameraConfig {
-            height_offset: -0.5,
-        })
         .id();
 
-    commands.spawn((
-        Camera3dBundle {
-            projection: Projection::Perspective(PerspectiveProjection {
-                fov: TAU / 4.0,
+    // Drawn after the world camera into the same target with a freshly cleared depth buffer,
+    // so the held weapon never clips into walls. The pixelation pass lives on this camera
+    // since it is the last one to render and sees the composited image.
+    let view_model_camera = commands
+        .spawn((
+            Camera3dBundle {
+                camera: Camera {
+                    order: 1,
+                    clear_color: ClearColorConfig::None,
+                    ..default()
+                },
+                projection: Projection::Perspective(PerspectiveProjection {
+                    fov: TAU / 6.0,
+                    ..default()
+                }),
+                deband_dither: DebandDither::Enabled,
+                exposure: Exposure::SUNLIGHT,
                 ..default()
-            }),
-            deband_dither: DebandDither::Enabled,
-            exposure: Exposure::SUNLIGHT,
-            ..default()
-        },
-        PostProcessSettings {
-            intensity: 5.0,
-            block_size: 4.0,
-        },
-        FogSettings {
-            color: Color::BLACK,
-            falloff: FogFalloff::from_visibility_colors(
-                20.0, // distance in world units up to which objects retain visibility (>= 5% contrast)
-                Color::srgb_u8(0x29, 0x27, 0x4c), // atmospheric extinction color (after light is lost due to absorption by atmospheric particles)
-                Color::srgb_u8(0x55, 0x5e, 0x88), // atmospheric inscattering color (light gained due to scattering from the sun)
-            ),
-            ..default()
-        },
-        RenderPlayer { logical_entity },
-    ));
+            },
+            PostProcessSettings {
+                intensity: settings.post_process_intensity,
+                block_size: settings.pixelation,
+            },
+            view_model_render_layers(),
+            ViewModelCamera,
+        ))
+        .set_parent(world_camera)
+        .id();
 
-    commands.insert_resource(MainScene {
-        handle: assets.load("playground.glb"),
-        is_loaded: false,
-    });
+    commands
+        .spawn((
+            PbrBundle {
+                mesh: meshes.add(Cuboid::new(0.08, 0.1, 0.5)),
+                material: materials.add(Color::srgb_u8(0x3a, 0x38, 0x5c)),
+                ..default()
+            },
+            view_model_render_layers(),
+            ViewModel::default(),
+        ))
+        .set_parent(view_model_camera);
+
+    if let Some(level) = registry.levels.first() {
+        load_level_events.send(LoadLevel {
+            id: level.id.clone(),
+        });
+    }
 
-    commands.spawn(
+    commands.spawn((
         TextBundle::from_section(
             "",
             TextStyle {
@@ -138,66 +236,47 @@ fn setup(mut commands: Commands, mut window: Query<&mut Window>, assets: Res<Ass
             left: Val::Px(5.0),
             ..default()
         }),
-    );
-}
-
-fn respawn(mut query: Query<(&mut Transform, &mut Velocity)>) {
-    for (mut transform, mut velocity) in &mut query {
-        if transform.translation.y > -50.0 {
-            continue;
-        }
-
-        velocity.linvel = Vec3::ZERO;
-        transform.translation = SPAWN_POINT;
-    }
+        HudText,
+    ));
 }
 
-#[derive(Resource)]
-struct MainScene {
-    handle: Handle<Gltf>,
-    is_loaded: bool,
+/// Steps physics exactly once per fixed update so runs can be replayed tick for tick.
+fn configure_physics(
+    mut rapier_configuration: ResMut<RapierConfiguration>,
+    fixed_time: Res<Time<Fixed>>,
+) {
+    rapier_configuration.timestep_mode = TimestepMode::Fixed {
+        dt: fixed_time.timestep().as_secs_f32(),
+        substeps: 1,
+    };
 }
 
-fn scene_colliders(
+fn respawn(
     mut commands: Commands,
-    mut main_scene: ResMut<MainScene>,
-    gltf_assets: Res<Assets<Gltf>>,
-    gltf_mesh_assets: Res<Assets<GltfMesh>>,
-    gltf_node_assets: Res<Assets<GltfNode>>,
-    mesh_assets: Res<Assets<Mesh>>,
+    time: Res<Time>,
+    window_query: Query<&Window>,
+    mut respawn_events: EventWriter<RespawnPlayer>,
+    mut query: Query<(Entity, &mut Dead, &mut Health, &mut FpsController), With<LogicalPlayer>>,
 ) {
-    if main_scene.is_loaded {
-        return;
-    }
-
-    let gltf = gltf_assets.get(&main_scene.handle);
-
-    if let Some(gltf) = gltf {
-        let scene = gltf.scenes.first().unwrap().clone();
-        commands.spawn(SceneBundle { scene, ..default() });
-        for node in &gltf.nodes {
-            let node = gltf_node_assets.get(node).unwrap();
-            if let Some(gltf_mesh) = node.mesh.clone() {
-                let gltf_mesh = gltf_mesh_assets.get(&gltf_mesh).unwrap();
-                for mesh_primitive in &gltf_mesh.primitives {
-                    let mesh = mesh_assets.get(&mesh_primitive.mesh).unwrap();
-                    commands.spawn((
-                        Collider::from_bevy_mesh(mesh, &ComputedColliderShape::TriMesh).unwrap(),
-                        RigidBody::Fixed,
-                        TransformBundle::from_transform(node.transform),
-                    ));
-                }
-            }
+    for (entity, mut dead, mut health, mut controller) in &mut query {
+        if !dead.respawn_timer.tick(time.delta()).finished() {
+            continue;
         }
-        main_scene.is_loaded = true;
+
+        health.current = health.max;
+        // Only hand control back if the cursor wasn't released while dead
+        controller.enable_input = window_query
+            .iter()
+            .any(|window| window.cursor.grab_mode != CursorGrabMode::None);
+        commands.entity(entity).remove::<Dead>();
+        respawn_events.send(RespawnPlayer);
     }
 }
 
 fn manage_cursor(
     btn: Res<ButtonInput<MouseButton>>,
-    key: Res<ButtonInput<KeyCode>>,
     mut window_query: Query<&mut Window>,
-    mut controller_query: Query<&mut FpsController>,
+    mut controller_query: Query<&mut FpsController, (With<LogicalPlayer>, Without<Dead>)>,
 ) {
     for mut window in &mut window_query {
         if btn.just_pressed(MouseButton::Left) {
@@ -207,12 +286,5 @@ fn manage_cursor(
                 controller.enable_input = true;
             }
         }
-        if key.just_pressed(KeyCode::Escape) {
-            window.cursor.grab_mode = CursorGrabMode::None;
-            window.cursor.visible = true;
-            for mut controller in &mut controller_query {
-                controller.enable_input = false;
-            }
-        }
     }
 }
@@ -0,0 +1,321 @@
+use bevy::prelude::*;
+
+use crate::{
+    level::{LevelRegistry, LoadLevel, MainScene},
+    settings::Settings,
+    state::{can_resume, GameState},
+};
+
+const BUTTON_COLOR: Color = Color::srgba(0.1, 0.09, 0.24, 0.9);
+const HOVERED_COLOR: Color = Color::srgba(0.2, 0.19, 0.4, 0.9);
+
+/// Menu shown while [`GameState::Paused`], with resume, settings, map select and quit.
+pub struct PauseMenuPlugin;
+
+impl Plugin for PauseMenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(GameState::Paused), spawn_pause_menu)
+            .add_systems(OnExit(GameState::Paused), despawn_pause_menu)
+            .add_systems(
+                Update,
+                (menu_buttons, update_setting_values)
+                    .chain()
+                    .run_if(in_state(GameState::Paused)),
+            );
+    }
+}
+
+#[derive(Component)]
+pub struct PauseMenu;
+
+/// A panel of the menu, only the current one is shown. Every pause starts on
+/// [`MenuPage::Main`].
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MenuPage {
+    Main,
+    Settings,
+    Maps,
+}
+
+#[derive(Component, Clone, Debug, PartialEq)]
+pub enum MenuButton {
+    Resume,
+    Open(MenuPage),
+    Quit,
+    LoadLevel(String),
+    /// Steps a setting up or down
+    Adjust(SettingKind, f32),
+}
+
+/// The settings that can be changed from the menu.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SettingKind {
+    Sensitivity,
+    Fov,
+    Pixelation,
+    Volume,
+}
+
+impl SettingKind {
+    const ALL: [SettingKind; 4] = [
+        SettingKind::Sensitivity,
+        SettingKind::Fov,
+        SettingKind::Pixelation,
+        SettingKind::Volume,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            SettingKind::Sensitivity => "Sensitivity",
+            SettingKind::Fov => "FOV",
+            SettingKind::Pixelation => "Pixelation",
+            SettingKind::Volume => "Volume",
+        }
+    }
+
+    /// How much one press of a button changes the setting.
+    fn step(self) -> f32 {
+        match self {
+            SettingKind::Sensitivity => 0.0001,
+            SettingKind::Fov => 5.0,
+            SettingKind::Pixelation => 1.0,
+            SettingKind::Volume => 0.1,
+        }
+    }
+
+    fn range(self) -> (f32, f32) {
+        match self {
+            SettingKind::Sensitivity => (0.0001, 0.01),
+            SettingKind::Fov => (50.0, 130.0),
+            SettingKind::Pixelation => (1.0, 16.0),
+            SettingKind::Volume => (0.0, 1.0),
+        }
+    }
+
+    fn value(self, settings: &mut Settings) -> &mut f32 {
+        match self {
+            SettingKind::Sensitivity => &mut settings.sensitivity,
+            SettingKind::Fov => &mut settings.fov,
+            SettingKind::Pixelation => &mut settings.pixelation,
+            SettingKind::Volume => &mut settings.volume,
+        }
+    }
+
+    fn display(self, settings: &Settings) -> String {
+        match self {
+            SettingKind::Sensitivity => format!("{:.4}", settings.sensitivity),
+            SettingKind::Fov => format!("{:.0}", settings.fov),
+            SettingKind::Pixelation => format!("{:.0}", settings.pixelation),
+            SettingKind::Volume => format!("{:.0}%", settings.volume * 100.0),
+        }
+    }
+}
+
+pub fn spawn_pause_menu(
+    mut commands: Commands,
+    assets: Res<AssetServer>,
+    registry: Res<LevelRegistry>,
+    settings: Res<Settings>,
+) {
+    let text_style = TextStyle {
+        font: assets.load("font.ttf"),
+        font_size: 32.0,
+        color: Color::WHITE,
+    };
+    let page_style = Style {
+        flex_direction: FlexDirection::Column,
+        align_items: AlignItems::Center,
+        row_gap: Val::Px(8.0),
+        ..default()
+    };
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: Color::srgba(0.0, 0.0, 0.0, 0.6).into(),
+                z_index: ZIndex::Global(5),
+                ..default()
+            },
+            PauseMenu,
+        ))
+        .with_children(|root| {
+            root.spawn((
+                NodeBundle {
+                    style: page_style.clone(),
+                    ..default()
+                },
+                MenuPage::Main,
+            ))
+            .with_children(|page| {
+                page.spawn(TextBundle::from_section("Paused", text_style.clone()));
+                spawn_button(page, &text_style, "Resume", MenuButton::Resume);
+                spawn_button(
+                    page,
+                    &text_style,
+                    "Settings",
+                    MenuButton::Open(MenuPage::Settings),
+                );
+                spawn_button(page, &text_style, "Maps", MenuButton::Open(MenuPage::Maps));
+                spawn_button(page, &text_style, "Quit", MenuButton::Quit);
+            });
+
+            root.spawn((
+                NodeBundle {
+                    style: Style {
+                        display: Display::None,
+                        ..page_style.clone()
+                    },
+                    ..default()
+                },
+                MenuPage::Settings,
+            ))
+            .with_children(|page| {
+                for kind in SettingKind::ALL {
+                    page.spawn(NodeBundle {
+                        style: Style {
+                            align_items: AlignItems::Center,
+                            column_gap: Val::Px(8.0),
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .with_children(|row| {
+                        row.spawn(TextBundle::from_section(kind.label(), text_style.clone()));
+                        spawn_button(row, &text_style, "-", MenuButton::Adjust(kind, -1.0));
+                        row.spawn((
+                            TextBundle::from_section(kind.display(&settings), text_style.clone()),
+                            kind,
+                        ));
+                        spawn_button(row, &text_style, "+", MenuButton::Adjust(kind, 1.0));
+                    });
+                }
+                spawn_button(page, &text_style, "Back", MenuButton::Open(MenuPage::Main));
+            });
+
+            root.spawn((
+                NodeBundle {
+                    style: Style {
+                        display: Display::None,
+                        ..page_style
+                    },
+                    ..default()
+                },
+                MenuPage::Maps,
+            ))
+            .with_children(|page| {
+                for level in &registry.levels {
+                    spawn_button(
+                        page,
+                        &text_style,
+                        &level.name,
+                        MenuButton::LoadLevel(level.id.clone()),
+                    );
+                }
+                spawn_button(page, &text_style, "Back", MenuButton::Open(MenuPage::Main));
+            });
+        });
+}
+
+fn spawn_button(
+    parent: &mut ChildBuilder,
+    text_style: &TextStyle,
+    label: &str,
+    action: MenuButton,
+) {
+    parent
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    padding: UiRect::axes(Val::Px(16.0), Val::Px(4.0)),
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                background_color: BUTTON_COLOR.into(),
+                ..default()
+            },
+            action,
+        ))
+        .with_children(|button| {
+            button.spawn(TextBundle::from_section(label, text_style.clone()));
+        });
+}
+
+pub fn despawn_pause_menu(mut commands: Commands, menu_query: Query<Entity, With<PauseMenu>>) {
+    for entity in &menu_query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub fn menu_buttons(
+    mut settings: ResMut<Settings>,
+    main_scene: Res<MainScene>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut load_level_events: EventWriter<LoadLevel>,
+    mut exit_events: EventWriter<AppExit>,
+    mut button_query: Query<
+        (&Interaction, &MenuButton, &mut BackgroundColor),
+        Changed<Interaction>,
+    >,
+    mut page_query: Query<(&MenuPage, &mut Style)>,
+) {
+    for (interaction, button, mut background) in &mut button_query {
+        *background = match interaction {
+            Interaction::Hovered | Interaction::Pressed => HOVERED_COLOR,
+            Interaction::None => BUTTON_COLOR,
+        }
+        .into();
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+
+        match button {
+            MenuButton::Resume => {
+                if can_resume(&main_scene) {
+                    next_state.set(GameState::InGame);
+                }
+            }
+            MenuButton::Open(open) => {
+                for (menu_page, mut style) in &mut page_query {
+                    style.display = if menu_page == open {
+                        Display::Flex
+                    } else {
+                        Display::None
+                    };
+                }
+            }
+            MenuButton::Quit => {
+                exit_events.send(AppExit::Success);
+            }
+            // Leaves the menu through GameState::Loading
+            MenuButton::LoadLevel(id) => {
+                load_level_events.send(LoadLevel { id: id.clone() });
+            }
+            MenuButton::Adjust(kind, direction) => {
+                let (min, max) = kind.range();
+                let value = kind.value(&mut settings);
+                *value = (*value + kind.step() * direction).clamp(min, max);
+            }
+        }
+    }
+}
+
+pub fn update_setting_values(
+    settings: Res<Settings>,
+    mut value_query: Query<(&SettingKind, &mut Text)>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    for (kind, mut text) in &mut value_query {
+        text.sections[0].value = kind.display(&settings);
+    }
+}
@@ -0,0 +1,488 @@
+use std::{collections::VecDeque, io, net::SocketAddr};
+
+use bevy::{app::FixedMain, prelude::*, time::Real, utils::HashMap, window::CursorGrabMode};
+use bevy_rapier3d::prelude::*;
+
+use super::{
+    model_scale,
+    protocol::{ClientMessage, PlayerState, ServerMessage, INPUT_REDUNDANCY, PROTOCOL_VERSION},
+    transport::Transport,
+    PlayerModel, RemotePlayer, RemotePlayerModel, TIMEOUT,
+};
+use crate::{
+    health::{Dead, Health},
+    level::{LoadLevel, MainScene, RequiredLevel},
+    player::{FpsController, FpsControllerInput, LogicalPlayer},
+    preset::ActivePreset,
+    replay::InputFrame,
+    spawn::Enemy,
+};
+
+/// How far behind the newest snapshot remote players are drawn, in ticks, so there is
+/// usually a later snapshot to interpolate towards.
+const INTERPOLATION_DELAY: f32 = 6.0;
+/// Prediction errors below this are left alone.
+const CORRECTION_TOLERANCE: f32 = 0.01;
+/// Unacknowledged inputs kept for replaying, older ones are dropped.
+const MAX_UNACKNOWLEDGED: usize = 64;
+/// Snapshots kept per remote player.
+const SNAPSHOT_BUFFER: usize = 32;
+
+#[derive(Resource)]
+pub struct NetClient {
+    pub transport: Transport,
+    pub server: SocketAddr,
+    /// Assigned by the server's welcome
+    pub id: Option<u32>,
+    /// Tick of the last input sent
+    pub tick: u32,
+    /// Inputs the server hasn't acknowledged yet, by tick, the latest are resent with every
+    /// packet
+    pub inputs: VecDeque<(u32, InputFrame)>,
+    /// Predicted state after each input the server hasn't acknowledged yet
+    pub predictions: VecDeque<(u32, PlayerState)>,
+    /// The server's state for the local player after the acknowledged input, waiting for
+    /// [`client_reconcile`]
+    pub correction: Option<(u32, PlayerState)>,
+    /// Set while [`client_reconcile`] replays inputs through the fixed schedule
+    pub resimulating: bool,
+    pub server_tick: u32,
+    /// Server tick remote players are drawn at
+    pub interpolation_tick: f32,
+    /// [`Time<Real>`] elapsed seconds when the server was last heard from
+    pub last_heard: f32,
+    /// [`Time<Real>`] elapsed seconds of the last hello
+    pub last_hello: Option<f32>,
+    /// Level the server is on, as of the newest snapshot
+    pub level: Option<String>,
+    /// Whether the server has the local player dead, as of the newest snapshot
+    pub dead: bool,
+}
+
+impl NetClient {
+    pub fn connect(server: SocketAddr) -> io::Result<Self> {
+        let local = if server.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+        Ok(Self {
+            transport: Transport::bind(local)?,
+            server,
+            id: None,
+            tick: 0,
+            inputs: VecDeque::new(),
+            predictions: VecDeque::new(),
+            correction: None,
+            resimulating: false,
+            server_tick: 0,
+            interpolation_tick: 0.0,
+            last_heard: 0.0,
+            last_hello: None,
+            level: None,
+            dead: false,
+        })
+    }
+}
+
+/// Keeps saying hello until the server answers, and starts over when it goes quiet.
+pub fn client_hello(time: Res<Time<Real>>, mut client: ResMut<NetClient>) {
+    let now = time.elapsed_seconds();
+    if client.id.is_some() && now - client.last_heard > TIMEOUT {
+        warn!("Lost connection to {}", client.server);
+        client.id = None;
+    }
+    if client.id.is_some() || client.last_hello.is_some_and(|last| now - last < 1.0) {
+        return;
+    }
+
+    client.last_hello = Some(now);
+    let server = client.server;
+    client.transport.send(
+        &ClientMessage::Hello {
+            version: PROTOCOL_VERSION,
+        },
+        server,
+    );
+}
+
+/// Buffered server states of a remote player, by server tick.
+#[derive(Component, Default)]
+pub struct Interpolated {
+    pub snapshots: VecDeque<(u32, PlayerState)>,
+}
+
+#[allow(clippy::type_complexity)]
+pub fn client_receive(
+    mut commands: Commands,
+    time: Res<Time<Real>>,
+    model: Res<PlayerModel>,
+    mut client: ResMut<NetClient>,
+    mut active_preset: ResMut<ActivePreset>,
+    mut remote_query: Query<(Entity, &RemotePlayer, &mut Interpolated)>,
+) {
+    let client = &mut *client;
+    let mut joined = HashMap::<u32, Interpolated>::new();
+
+    while let Some((message, from)) = client.transport.receive::<ServerMessage>() {
+        if from != client.server {
+            continue;
+        }
+        client.last_heard = time.elapsed_seconds();
+
+        let (tick, ack, players, level) = match message {
+            ServerMessage::Welcome { id } => {
+                if client.id.is_none() {
+                    info!("Joined as player {id}");
+                    client.interpolation_tick = 0.0;
+                }
+                client.id = Some(id);
+                continue;
+            }
+            ServerMessage::Snapshot {
+                tick,
+                ack,
+                players,
+                tuning,
+                level,
+            } => {
+                // Predicting with anything else than the server's tuning would never line up
+                if active_preset.tuning_override != Some(tuning) {
+                    active_preset.tuning_override = Some(tuning);
+                }
+                (tick, ack, players, level)
+            }
+        };
+        let Some(id) = client.id else {
+            continue;
+        };
+        // Late snapshots are still fine to interpolate from, but can't correct anything
+        let newest = tick > client.server_tick;
+        if newest {
+            client.server_tick = tick;
+            client.level = Some(level);
+        }
+
+        for state in &players {
+            if state.id == id {
+                if newest {
+                    client.correction = Some((ack, *state));
+                    client.dead = state.dead;
+                }
+                continue;
+            }
+
+            let mut remote = remote_query
+                .iter_mut()
+                .find(|(_, remote, _)| remote.id == state.id);
+            let interpolated = match &mut remote {
+                Some((_, _, interpolated)) => &mut **interpolated,
+                // Several snapshots can arrive before a new player is spawned
+                None => joined.entry(state.id).or_default(),
+            };
+            let position = interpolated
+                .snapshots
+                .partition_point(|(snapshot_tick, _)| *snapshot_tick < tick);
+            interpolated.snapshots.insert(position, (tick, *state));
+            if interpolated.snapshots.len() > SNAPSHOT_BUFFER {
+                interpolated.snapshots.pop_front();
+            }
+        }
+
+        // Players missing from the newest snapshot have left
+        if newest {
+            for (entity, remote, _) in &remote_query {
+                if !players.iter().any(|state| state.id == remote.id) {
+                    commands.entity(entity).despawn_recursive();
+                }
+            }
+            joined.retain(|id, _| players.iter().any(|state| state.id == *id));
+        }
+    }
+
+    for (id, interpolated) in joined {
+        let Some(&(_, state)) = interpolated.snapshots.back() else {
+            continue;
+        };
+        commands
+            .spawn((
+                SpatialBundle::from_transform(Transform::from_translation(state.position)),
+                RemotePlayer { id },
+                Enemy,
+                interpolated,
+            ))
+            .with_children(|player| {
+                player.spawn((model.bundle(), RemotePlayerModel));
+            });
+    }
+}
+
+/// Compares the server's state for the last acknowledged input with what was predicted for
+/// it. When they differ the player is rewound to the server's state and every input sent
+/// since is simulated again, running whole fixed ticks like [`seek_replay`].
+///
+/// [`seek_replay`]: crate::replay::seek_replay
+pub fn client_reconcile(world: &mut World) {
+    let mut client = world.resource_mut::<NetClient>();
+    let Some((ack, server_state)) = client.correction.take() else {
+        return;
+    };
+    while client.inputs.front().is_some_and(|(tick, _)| *tick <= ack) {
+        client.inputs.pop_front();
+    }
+    while client
+        .predictions
+        .front()
+        .is_some_and(|(tick, _)| *tick < ack)
+    {
+        client.predictions.pop_front();
+    }
+    let Some(&(tick, predicted)) = client.predictions.front() else {
+        return;
+    };
+    if tick != ack {
+        return;
+    }
+    client.predictions.pop_front();
+    if server_state.position.distance(predicted.position) < CORRECTION_TOLERANCE {
+        return;
+    }
+    client.predictions.clear();
+
+    let Some(id) = client.id else {
+        return;
+    };
+    let replay: Vec<(u32, InputFrame)> = client.inputs.iter().copied().collect();
+
+    let mut query = world.query_filtered::<(
+        &mut FpsController,
+        &mut FpsControllerInput,
+        &mut Collider,
+        &mut Transform,
+        &mut Velocity,
+    ), With<LogicalPlayer>>();
+    let Ok((mut controller, input, mut collider, mut transform, mut velocity)) =
+        query.get_single_mut(world)
+    else {
+        return;
+    };
+    let current_input = InputFrame::capture(&input);
+    server_state.apply(
+        &mut controller,
+        &mut collider,
+        &mut transform,
+        &mut velocity,
+    );
+
+    world.resource_mut::<NetClient>().resimulating = true;
+    *world.resource_mut::<Time>() = world.resource::<Time<Fixed>>().as_generic();
+    let last_tick = replay.last().map(|(tick, _)| *tick);
+    for (tick, frame) in replay {
+        if let Ok((_, mut input, ..)) = query.get_single_mut(world) {
+            frame.apply(&mut input);
+        }
+        world.run_schedule(FixedMain);
+
+        // The prediction for the last input is taken by the next tick's `client_send_input`
+        if Some(tick) == last_tick {
+            continue;
+        }
+        if let Ok((controller, _, _, transform, velocity)) = query.get_single(world) {
+            let state = PlayerState::capture(id, controller, transform, velocity);
+            world
+                .resource_mut::<NetClient>()
+                .predictions
+                .push_back((tick, state));
+        }
+    }
+    *world.resource_mut::<Time>() = world.resource::<Time<Virtual>>().as_generic();
+
+    if let Ok((_, mut input, ..)) = query.get_single_mut(world) {
+        current_input.apply(&mut input);
+    }
+    world.resource_mut::<NetClient>().resimulating = false;
+}
+
+/// Whether [`client_reconcile`] is replaying inputs, so fixed systems that shouldn't see the
+/// same tick twice can skip.
+pub fn resimulating(client: Option<Res<NetClient>>) -> bool {
+    client.is_some_and(|client| client.resimulating)
+}
+
+/// Loads the server's level, and keeps any other from being loaded while connected.
+pub fn client_follow_level(
+    client: Res<NetClient>,
+    main_scene: Res<MainScene>,
+    mut required: ResMut<RequiredLevel>,
+    mut load_level_events: EventWriter<LoadLevel>,
+) {
+    let Some(level) = &client.level else {
+        return;
+    };
+    if required.0.as_ref() == Some(level) {
+        return;
+    }
+    required.0 = Some(level.clone());
+    if main_scene.level != *level {
+        info!("Server is on {level}");
+        load_level_events.send(LoadLevel { id: level.clone() });
+    }
+}
+
+/// Kills and revives the local player when the server does, it's the server that respawns
+/// them.
+#[allow(clippy::type_complexity)]
+pub fn client_sync_death(
+    mut commands: Commands,
+    client: Res<NetClient>,
+    window_query: Query<&Window>,
+    mut player_query: Query<
+        (
+            Entity,
+            Has<Dead>,
+            &mut Health,
+            &mut FpsController,
+            &mut FpsControllerInput,
+        ),
+        With<LogicalPlayer>,
+    >,
+) {
+    let Ok((entity, dead, mut health, mut controller, mut input)) = player_query.get_single_mut()
+    else {
+        return;
+    };
+    match (client.dead, dead) {
+        (true, false) => {
+            health.current = 0.0;
+            controller.enable_input = false;
+            input.movement = Vec3::ZERO;
+            input.jump = false;
+            input.crouch = false;
+            commands.entity(entity).insert(Dead {
+                respawn_timer: Timer::default(),
+            });
+        }
+        (false, true) => {
+            health.current = health.max;
+            controller.enable_input = window_query
+                .iter()
+                .any(|window| window.cursor.grab_mode != CursorGrabMode::None);
+            commands.entity(entity).remove::<Dead>();
+        }
+        _ => {}
+    }
+}
+
+/// Predicts the local player like offline play, remembering the result of the last tick and
+/// sending this tick's input to the server.
+pub fn client_send_input(
+    mut client: ResMut<NetClient>,
+    player_query: Query<
+        (&FpsController, &FpsControllerInput, &Transform, &Velocity),
+        With<LogicalPlayer>,
+    >,
+) {
+    let Some(id) = client.id else {
+        return;
+    };
+    if client.resimulating {
+        return;
+    }
+    let Ok((controller, input, transform, velocity)) = player_query.get_single() else {
+        return;
+    };
+
+    // The physics step for the previous input has run by now
+    let tick = client.tick;
+    let state = PlayerState::capture(id, controller, transform, velocity);
+    client.predictions.push_back((tick, state));
+
+    client.tick += 1;
+    let tick = client.tick;
+    client.inputs.push_back((tick, InputFrame::capture(input)));
+    if client.inputs.len() > MAX_UNACKNOWLEDGED {
+        client.inputs.pop_front();
+    }
+
+    let skip = client.inputs.len().saturating_sub(INPUT_REDUNDANCY);
+    let message = ClientMessage::Input {
+        tick,
+        frames: client
+            .inputs
+            .iter()
+            .skip(skip)
+            .map(|(_, frame)| *frame)
+            .collect(),
+    };
+    let server = client.server;
+    client.transport.send(&message, server);
+}
+
+/// Draws remote players between the two snapshots around the interpolation tick.
+#[allow(clippy::type_complexity)]
+pub fn client_interpolate(
+    time: Res<Time>,
+    fixed_time: Res<Time<Fixed>>,
+    mut client: ResMut<NetClient>,
+    mut remote_query: Query<(&Interpolated, &mut Transform, &mut Visibility, &Children)>,
+    mut model_query: Query<&mut Transform, (With<RemotePlayerModel>, Without<Interpolated>)>,
+) {
+    let ticks_per_second = 1.0 / fixed_time.timestep().as_secs_f32();
+    let target = client.server_tick as f32 - INTERPOLATION_DELAY;
+    client.interpolation_tick += time.delta_seconds() * ticks_per_second;
+    // Drift slowly towards the target, but jump when far off after a stall
+    let drift = target - client.interpolation_tick;
+    if drift.abs() > INTERPOLATION_DELAY * 2.0 {
+        client.interpolation_tick = target;
+    } else {
+        client.interpolation_tick += drift * 0.1;
+    }
+    let render_tick = client.interpolation_tick;
+
+    for (interpolated, mut transform, mut visibility, children) in &mut remote_query {
+        let snapshots = &interpolated.snapshots;
+        let next = snapshots.partition_point(|(tick, _)| *tick as f32 <= render_tick);
+        let state = match (
+            next.checked_sub(1).map(|i| &snapshots[i]),
+            snapshots.get(next),
+        ) {
+            (Some((from_tick, from)), Some((to_tick, to))) => {
+                let fraction = (render_tick - *from_tick as f32) / (to_tick - from_tick) as f32;
+                let yaw =
+                    Quat::from_rotation_y(from.yaw).slerp(Quat::from_rotation_y(to.yaw), fraction);
+                PlayerState {
+                    position: from.position.lerp(to.position, fraction),
+                    yaw: yaw.to_euler(EulerRot::YXZ).0,
+                    height: from.height.lerp(to.height, fraction),
+                    ..*to
+                }
+            }
+            // Out of snapshots, hold the nearest one
+            (Some((_, state)), None) | (None, Some((_, state))) => *state,
+            (None, None) => continue,
+        };
+
+        *visibility = if state.dead {
+            Visibility::Hidden
+        } else {
+            Visibility::Inherited
+        };
+        transform.translation = state.position;
+        transform.rotation = Quat::from_rotation_y(state.yaw);
+        for &child in children {
+            if let Ok(mut model) = model_query.get_mut(child) {
+                model.scale = model_scale(state.height);
+            }
+        }
+    }
+}
+
+pub fn client_disconnect_on_exit(
+    mut exit_events: EventReader<AppExit>,
+    mut client: ResMut<NetClient>,
+) {
+    if exit_events.read().count() > 0 && client.id.is_some() {
+        let server = client.server;
+        client.transport.send(&ClientMessage::Disconnect, server);
+    }
+}
@@ -0,0 +1,101 @@
+use std::{
+    collections::VecDeque,
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::*;
+use rand::Rng;
+
+/// Extra seconds a packet picked for reordering is held back, so the ones after it overtake it.
+const REORDER_DELAY: f32 = 0.05;
+
+/// Simulated network conditions for trying the netcode on a bad connection over loopback.
+/// Applied to both the packets a [`Transport`](super::transport::Transport) sends and the ones
+/// it receives, so the round trip sees twice the latency.
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq)]
+pub struct LinkConditioner {
+    /// Seconds every packet is delayed by
+    pub latency: f32,
+    /// Up to this many seconds added to or taken off the latency at random
+    pub jitter: f32,
+    /// Chance of dropping a packet, from 0 to 1
+    pub loss: f32,
+    /// Chance of delivering a packet twice
+    pub duplication: f32,
+    /// Chance of holding a packet back behind later ones
+    pub reordering: f32,
+}
+
+impl LinkConditioner {
+    pub fn is_enabled(&self) -> bool {
+        *self != Self::default()
+    }
+
+    /// When each copy of a packet sent at `now` arrives, none if it's lost.
+    pub fn arrivals(&self, now: Instant) -> Vec<Instant> {
+        let mut rng = rand::thread_rng();
+        if rng.gen::<f32>() < self.loss {
+            return Vec::new();
+        }
+
+        let copies = if rng.gen::<f32>() < self.duplication {
+            2
+        } else {
+            1
+        };
+        let jitter = self.jitter.max(0.0);
+        (0..copies)
+            .map(|_| {
+                let mut delay = self.latency + rng.gen_range(-jitter..=jitter);
+                if rng.gen::<f32>() < self.reordering {
+                    delay += REORDER_DELAY;
+                }
+                now + Duration::from_secs_f32(delay.max(0.0))
+            })
+            .collect()
+    }
+}
+
+struct DelayedPacket {
+    arrival: Instant,
+    bytes: Vec<u8>,
+    address: SocketAddr,
+}
+
+/// Packets held back by a [`LinkConditioner`], in order of arrival.
+#[derive(Default)]
+pub struct DelayQueue {
+    packets: VecDeque<DelayedPacket>,
+}
+
+impl DelayQueue {
+    pub fn push(&mut self, conditioner: &LinkConditioner, bytes: Vec<u8>, address: SocketAddr) {
+        for arrival in conditioner.arrivals(Instant::now()) {
+            let index = self
+                .packets
+                .partition_point(|packet| packet.arrival <= arrival);
+            self.packets.insert(
+                index,
+                DelayedPacket {
+                    arrival,
+                    bytes: bytes.clone(),
+                    address,
+                },
+            );
+        }
+    }
+
+    /// The next packet whose time has come.
+    pub fn pop(&mut self) -> Option<(Vec<u8>, SocketAddr)> {
+        if self.packets.front()?.arrival > Instant::now() {
+            return None;
+        }
+        let packet = self.packets.pop_front()?;
+        Some((packet.bytes, packet.address))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.packets.is_empty()
+    }
+}
@@ -0,0 +1,387 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use super::{client::NetClient, protocol::ClientMessage, server::NetServer, RemotePlayer};
+use crate::{
+    health::{DamageEvent, Dead},
+    player::{FpsController, LogicalPlayer},
+};
+
+/// Furthest back in time, in seconds, a shot is rewound to. Clients lagging more than this
+/// have to lead their targets.
+pub const MAX_REWIND: f32 = 0.25;
+const HITSCAN_RANGE: f32 = 1000.0;
+const HITSCAN_DAMAGE: f32 = 25.0;
+/// Eye height relative to the top of the collider, matching the camera's offset.
+const EYE_OFFSET: f32 = -0.5;
+/// Radius of the player collider shots are tested against.
+const PLAYER_RADIUS: f32 = 0.5;
+
+/// A hitscan shot waiting for the server to resolve it.
+pub struct Shot {
+    pub shooter: Entity,
+    /// Server tick the shooter was seeing other players at
+    pub view_tick: f32,
+    pub direction: Vec3,
+}
+
+/// Where a player's collider was after some server tick.
+#[derive(Clone, Copy, Debug)]
+pub struct HistoricalCollider {
+    pub entity: Entity,
+    pub position: Vec3,
+    pub height: f32,
+}
+
+/// Server side ring buffer of player colliders over the last [`MAX_REWIND`] seconds.
+#[derive(Resource, Default)]
+pub struct LagCompensation {
+    pub history: VecDeque<(u32, Vec<HistoricalCollider>)>,
+    pub shots: Vec<Shot>,
+}
+
+impl LagCompensation {
+    /// Colliders interpolated to a fractional tick, clamped to the recorded window.
+    pub fn rewind(&self, tick: f32) -> Vec<HistoricalCollider> {
+        let next = self
+            .history
+            .partition_point(|(history_tick, _)| *history_tick as f32 <= tick);
+        let (from_tick, from) = match next.checked_sub(1) {
+            Some(previous) => &self.history[previous],
+            None => match self.history.front() {
+                Some(oldest) => oldest,
+                None => return Vec::new(),
+            },
+        };
+        let Some((to_tick, to)) = self.history.get(next) else {
+            return from.clone();
+        };
+
+        let fraction = ((tick - *from_tick as f32) / (to_tick - from_tick) as f32).clamp(0.0, 1.0);
+        from.iter()
+            .map(|collider| {
+                let Some(later) = to.iter().find(|later| later.entity == collider.entity) else {
+                    return *collider;
+                };
+                HistoricalCollider {
+                    entity: collider.entity,
+                    position: collider.position.lerp(later.position, fraction),
+                    height: collider.height.lerp(later.height, fraction),
+                }
+            })
+            .collect()
+    }
+}
+
+pub fn eye_position(transform: &Transform, controller: &FpsController) -> Vec3 {
+    transform.translation + Vec3::Y * (controller.height * 0.5 + EYE_OFFSET)
+}
+
+pub fn aim_direction(controller: &FpsController) -> Vec3 {
+    Quat::from_euler(EulerRot::YXZ, controller.yaw, controller.pitch, 0.0) * Vec3::NEG_Z
+}
+
+/// Remembers every player's collider after the tick that was just simulated.
+#[allow(clippy::type_complexity)]
+pub fn record_lag_history(
+    server: Res<NetServer>,
+    fixed_time: Res<Time<Fixed>>,
+    mut lag_compensation: ResMut<LagCompensation>,
+    player_query: Query<
+        (Entity, &FpsController, &Transform),
+        Or<(With<LogicalPlayer>, With<RemotePlayer>)>,
+    >,
+) {
+    let colliders = player_query
+        .iter()
+        .map(|(entity, controller, transform)| HistoricalCollider {
+            entity,
+            position: transform.translation,
+            height: controller.height,
+        })
+        .collect();
+    lag_compensation.history.push_back((server.tick, colliders));
+
+    let max_ticks = (MAX_REWIND / fixed_time.timestep().as_secs_f32()).ceil() as usize + 1;
+    while lag_compensation.history.len() > max_ticks {
+        lag_compensation.history.pop_front();
+    }
+}
+
+/// The host's own shots see everyone where they are now.
+#[allow(clippy::type_complexity)]
+pub fn host_fire(
+    mouse_input: Res<ButtonInput<MouseButton>>,
+    server: Res<NetServer>,
+    mut lag_compensation: ResMut<LagCompensation>,
+    host_query: Query<(Entity, &FpsController), (With<LogicalPlayer>, Without<Dead>)>,
+) {
+    let Ok((entity, controller)) = host_query.get_single() else {
+        return;
+    };
+    if controller.enable_input && mouse_input.just_pressed(MouseButton::Left) {
+        lag_compensation.shots.push(Shot {
+            shooter: entity,
+            view_tick: server.tick as f32,
+            direction: aim_direction(controller),
+        });
+    }
+}
+
+/// Clients shoot at remote players as drawn, so the server is told which tick that was.
+pub fn client_fire(
+    mouse_input: Res<ButtonInput<MouseButton>>,
+    mut client: ResMut<NetClient>,
+    player_query: Query<&FpsController, (With<LogicalPlayer>, Without<Dead>)>,
+) {
+    let Ok(controller) = player_query.get_single() else {
+        return;
+    };
+    if client.id.is_none() || !controller.enable_input {
+        return;
+    }
+    if mouse_input.just_pressed(MouseButton::Left) {
+        let message = ClientMessage::Fire {
+            view_tick: client.interpolation_tick,
+            direction: aim_direction(controller),
+        };
+        let server = client.server;
+        client.transport.send(&message, server);
+    }
+}
+
+/// Resolves shots against players rewound to what the shooter saw, and against the level as
+/// it is.
+pub fn resolve_shots(
+    server: Res<NetServer>,
+    fixed_time: Res<Time<Fixed>>,
+    physics_context: Res<RapierContext>,
+    mut lag_compensation: ResMut<LagCompensation>,
+    shooter_query: Query<(&FpsController, &Transform), Without<Dead>>,
+    mut damage_events: EventWriter<DamageEvent>,
+) {
+    let max_rewind_ticks = MAX_REWIND / fixed_time.timestep().as_secs_f32();
+    let newest = server.tick as f32;
+    let shots: Vec<Shot> = lag_compensation.shots.drain(..).collect();
+
+    for shot in shots {
+        let Ok((controller, transform)) = shooter_query.get(shot.shooter) else {
+            continue;
+        };
+        let origin = eye_position(transform, controller);
+        let direction = shot.direction.normalize_or_zero();
+        if direction == Vec3::ZERO {
+            continue;
+        }
+
+        let wall = physics_context
+            .cast_ray(
+                origin,
+                direction,
+                HITSCAN_RANGE,
+                true,
+                QueryFilter::only_fixed(),
+            )
+            .map_or(HITSCAN_RANGE, |(_, time_of_impact)| time_of_impact);
+
+        let tick = shot.view_tick.clamp(newest - max_rewind_ticks, newest);
+        let hit = lag_compensation
+            .rewind(tick)
+            .into_iter()
+            .filter(|collider| collider.entity != shot.shooter)
+            .filter_map(|collider| {
+                let shape = Collider::cylinder(collider.height * 0.5, PLAYER_RADIUS);
+                let time_of_impact = shape.cast_ray(
+                    collider.position,
+                    Quat::IDENTITY,
+                    origin,
+                    direction,
+                    wall,
+                    true,
+                )?;
+                Some((collider.entity, time_of_impact))
+            })
+            .min_by(|(_, a), (_, b)| a.total_cmp(b));
+
+        if let Some((target, _)) = hit {
+            damage_events.send(DamageEvent {
+                target,
+                amount: HITSCAN_DAMAGE,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{Ipv4Addr, SocketAddr},
+        time::{Duration, Instant},
+    };
+
+    use bevy::{ecs::system::RunSystemOnce, time::Real};
+
+    use super::*;
+    use crate::{
+        net::{client, server, LinkConditioner, PlayerModel},
+        preset::ActivePreset,
+        spawn::{SpawnPoints, SpawnPolicy},
+    };
+
+    /// One way latency the client's link adds to everything it sends and receives.
+    const LATENCY: f32 = 0.05;
+    const TIMEOUT: Duration = Duration::from_secs(2);
+
+    fn player_model() -> PlayerModel {
+        PlayerModel {
+            mesh: Handle::default(),
+            material: Handle::default(),
+        }
+    }
+
+    fn server_world() -> World {
+        let mut world = World::new();
+        world.insert_resource(NetServer::bind(0).unwrap());
+        world.insert_resource(Time::<Real>::default());
+        world.insert_resource(Time::<Fixed>::default());
+        world.insert_resource(player_model());
+        world.init_resource::<SpawnPoints>();
+        world.init_resource::<SpawnPolicy>();
+        world.init_resource::<LagCompensation>();
+        world.init_resource::<RapierContext>();
+        world.init_resource::<Events<DamageEvent>>();
+        world
+    }
+
+    fn client_world(server: &World) -> World {
+        let port = server
+            .resource::<NetServer>()
+            .transport
+            .local_addr()
+            .unwrap()
+            .port();
+        let mut client = NetClient::connect(SocketAddr::from((Ipv4Addr::LOCALHOST, port))).unwrap();
+        client.transport.set_conditioner(LinkConditioner {
+            latency: LATENCY,
+            ..default()
+        });
+
+        let mut world = World::new();
+        world.insert_resource(client);
+        world.insert_resource(Time::<Real>::default());
+        world.insert_resource(player_model());
+        world.init_resource::<ActivePreset>();
+        world.spawn((LogicalPlayer, FpsController::default()));
+        world
+    }
+
+    /// Runs both sides until the client is welcomed, returning the shooter the server spawned
+    /// for it, moved to the origin looking down -Z.
+    fn connect(server: &mut World, client: &mut World) -> Entity {
+        client.run_system_once(client::client_hello);
+        let start = Instant::now();
+        while client.resource::<NetClient>().id.is_none() {
+            assert!(start.elapsed() < TIMEOUT, "client never got welcomed");
+            server.run_system_once(server::server_receive);
+            client.run_system_once(client::client_receive);
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        let shooter = server
+            .resource::<NetServer>()
+            .clients
+            .values()
+            .next()
+            .unwrap()
+            .entity;
+        server.get_mut::<Transform>(shooter).unwrap().translation = Vec3::ZERO;
+        shooter
+    }
+
+    /// Records `ticks` with the target crossing the shooter's line of fire at `crossing_tick`.
+    fn record_ticks(
+        server: &mut World,
+        target: Entity,
+        ticks: std::ops::RangeInclusive<u32>,
+        crossing_tick: u32,
+    ) {
+        for tick in ticks {
+            let x = (tick as f32 - crossing_tick as f32) * 0.5;
+            server.get_mut::<Transform>(target).unwrap().translation = Vec3::new(x, 0.0, -10.0);
+            server.resource_mut::<NetServer>().tick = tick;
+            server.run_system_once(record_lag_history);
+        }
+    }
+
+    fn spawn_target(server: &mut World) -> Entity {
+        server
+            .spawn((
+                RemotePlayer { id: 99 },
+                FpsController::default(),
+                Transform::default(),
+            ))
+            .id()
+    }
+
+    /// Fires straight ahead from the client seeing remote players at `view_tick`, and returns
+    /// who the server says got hit once the shot makes it across the link.
+    fn fire(server: &mut World, client: &mut World, view_tick: f32) -> Vec<Entity> {
+        let mut mouse_input = ButtonInput::<MouseButton>::default();
+        mouse_input.press(MouseButton::Left);
+        client.insert_resource(mouse_input);
+        client.resource_mut::<NetClient>().interpolation_tick = view_tick;
+        let sent = Instant::now();
+        client.run_system_once(client_fire);
+
+        while server.resource::<LagCompensation>().shots.is_empty() {
+            assert!(sent.elapsed() < TIMEOUT, "shot never arrived");
+            // Held back packets go out as the client polls its socket
+            client.run_system_once(client::client_receive);
+            server.run_system_once(server::server_receive);
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert!(sent.elapsed() >= Duration::from_secs_f32(LATENCY));
+
+        server.run_system_once(resolve_shots);
+        server
+            .resource_mut::<Events<DamageEvent>>()
+            .drain()
+            .map(|event| event.target)
+            .collect()
+    }
+
+    #[test]
+    fn shots_hit_players_where_the_shooter_saw_them() {
+        let mut server = server_world();
+        let mut client = client_world(&server);
+        connect(&mut server, &mut client);
+        let target = spawn_target(&mut server);
+        record_ticks(&mut server, target, 1..=15, 10);
+
+        // The target has moved 2.5 units on since tick 10, out of the line of fire
+        assert_eq!(fire(&mut server, &mut client, 10.0), vec![target]);
+        assert!(fire(&mut server, &mut client, 15.0).is_empty());
+    }
+
+    #[test]
+    fn shots_older_than_max_rewind_are_clamped() {
+        let mut server = server_world();
+        let mut client = client_world(&server);
+        connect(&mut server, &mut client);
+        let target = spawn_target(&mut server);
+
+        let timestep = server.resource::<Time<Fixed>>().timestep().as_secs_f32();
+        let max_rewind_ticks = (MAX_REWIND / timestep) as u32;
+        let newest = 40;
+        let oldest = newest - max_rewind_ticks;
+        record_ticks(&mut server, target, 1..=newest, oldest);
+
+        let history = &server.resource::<LagCompensation>().history;
+        assert!(history.front().unwrap().0 >= oldest - 1);
+
+        // At tick 10 the target was far off to the side, but the shot can't go back that far
+        assert_eq!(fire(&mut server, &mut client, 10.0), vec![target]);
+    }
+}
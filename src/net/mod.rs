@@ -0,0 +1,214 @@
+mod client;
+mod conditioner;
+mod lag_compensation;
+mod protocol;
+mod server;
+mod transport;
+
+use std::net::SocketAddr;
+
+use bevy::prelude::*;
+
+pub use client::{resimulating, NetClient};
+pub use conditioner::LinkConditioner;
+pub use lag_compensation::LagCompensation;
+pub use server::NetServer;
+pub use transport::Transport;
+
+use crate::{player::fps_controller_move, preset::ActivePreset};
+
+/// Seconds without hearing from the other side before giving up on it.
+const TIMEOUT: f32 = 5.0;
+
+/// `--host <port>` runs a listen server the local player plays on, `--connect <address>` joins
+/// one, e.g. `--host 7777` and `--connect 127.0.0.1:7777` over loopback. Left click fires a
+/// hitscan shot the server resolves against players as the shooter saw them. The
+/// [`LinkConditioner`] resource simulates a worse connection than loopback.
+pub struct NetPlugin;
+
+impl Plugin for NetPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LagCompensation>()
+            .init_resource::<LinkConditioner>()
+            .add_systems(Startup, (setup_player_model, lock_tuning))
+            .add_systems(
+                Update,
+                apply_link_conditioner
+                    .before(server::server_receive)
+                    .before(client::client_receive),
+            )
+            .add_systems(
+                Update,
+                (
+                    server::server_receive,
+                    server::server_timeouts,
+                    server::server_respawn_players,
+                    lag_compensation::host_fire,
+                )
+                    .chain()
+                    .run_if(resource_exists::<NetServer>),
+            )
+            .add_systems(
+                FixedUpdate,
+                (
+                    server::server_send_snapshots,
+                    lag_compensation::record_lag_history,
+                    lag_compensation::resolve_shots,
+                    server::server_apply_inputs,
+                )
+                    .chain()
+                    .before(fps_controller_move)
+                    .run_if(resource_exists::<NetServer>),
+            )
+            .add_systems(
+                Update,
+                (
+                    client::client_hello,
+                    client::client_receive,
+                    client::client_follow_level,
+                    client::client_sync_death,
+                    client::client_reconcile,
+                    client::client_interpolate,
+                    lag_compensation::client_fire,
+                    client::client_disconnect_on_exit,
+                )
+                    .chain()
+                    .run_if(resource_exists::<NetClient>),
+            )
+            .add_systems(
+                FixedUpdate,
+                client::client_send_input
+                    .before(fps_controller_move)
+                    .run_if(resource_exists::<NetClient>),
+            );
+
+        match NetMode::from_args() {
+            NetMode::Offline => {}
+            NetMode::Host { port } => match NetServer::bind(port) {
+                Ok(server) => {
+                    // Port 0 lets the OS pick one
+                    match server.transport.local_addr() {
+                        Ok(address) => info!("Hosting on port {}", address.port()),
+                        Err(_) => info!("Hosting on port {port}"),
+                    }
+                    app.insert_resource(server);
+                }
+                Err(error) => error!("Couldn't host on port {port}: {error}"),
+            },
+            NetMode::Connect { address } => match NetClient::connect(address) {
+                Ok(client) => {
+                    info!("Connecting to {address}");
+                    app.insert_resource(client);
+                }
+                Err(error) => error!("Couldn't connect to {address}: {error}"),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NetMode {
+    Offline,
+    Host { port: u16 },
+    Connect { address: SocketAddr },
+}
+
+impl NetMode {
+    pub fn from_args() -> Self {
+        let mut args = std::env::args();
+        while let Some(arg) = args.next() {
+            let parsed = match arg.as_str() {
+                "--host" => args
+                    .next()
+                    .and_then(|port| port.parse().ok())
+                    .map(|port| NetMode::Host { port }),
+                "--connect" => args
+                    .next()
+                    .and_then(|address| address.parse().ok())
+                    .map(|address| NetMode::Connect { address }),
+                _ => continue,
+            };
+            match parsed {
+                Some(mode) => return mode,
+                None => warn!("Ignoring {arg} without a valid value"),
+            }
+        }
+        NetMode::Offline
+    }
+}
+
+/// Hands a changed [`LinkConditioner`] to whichever side is running.
+pub fn apply_link_conditioner(
+    conditioner: Res<LinkConditioner>,
+    server: Option<ResMut<NetServer>>,
+    client: Option<ResMut<NetClient>>,
+) {
+    if !conditioner.is_changed() {
+        return;
+    }
+    if let Some(mut server) = server {
+        server.transport.set_conditioner(*conditioner);
+    }
+    if let Some(mut client) = client {
+        client.transport.set_conditioner(*conditioner);
+    }
+}
+
+/// Keeps the local tuning from changing mid-session, a client takes the server's instead.
+pub fn lock_tuning(
+    server: Option<Res<NetServer>>,
+    client: Option<Res<NetClient>>,
+    mut active: ResMut<ActivePreset>,
+) {
+    if server.is_some() {
+        active.locked = Some("while hosting");
+    } else if client.is_some() {
+        active.locked = Some("while connected");
+    }
+}
+
+/// Another player in the session, identified by the id the server gave them.
+#[derive(Component)]
+pub struct RemotePlayer {
+    pub id: u32,
+}
+
+/// What other players look like.
+#[derive(Resource)]
+pub struct PlayerModel {
+    pub mesh: Handle<Mesh>,
+    pub material: Handle<StandardMaterial>,
+}
+
+/// Height of [`PlayerModel::mesh`], scaled to the player's crouch height.
+const PLAYER_MODEL_HEIGHT: f32 = 3.0;
+
+pub fn setup_player_model(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.insert_resource(PlayerModel {
+        mesh: meshes.add(Capsule3d::new(0.5, PLAYER_MODEL_HEIGHT - 1.0)),
+        material: materials.add(Color::srgb_u8(0xd0, 0x5a, 0x4e)),
+    });
+}
+
+impl PlayerModel {
+    /// Drawn as a child so the simulated body's transform isn't scaled with the crouch height.
+    pub fn bundle(&self) -> PbrBundle {
+        PbrBundle {
+            mesh: self.mesh.clone(),
+            material: self.material.clone(),
+            ..default()
+        }
+    }
+}
+
+/// Marks the [`PlayerModel`] child of a [`RemotePlayer`].
+#[derive(Component)]
+pub struct RemotePlayerModel;
+
+pub fn model_scale(height: f32) -> Vec3 {
+    Vec3::new(1.0, height / PLAYER_MODEL_HEIGHT, 1.0)
+}
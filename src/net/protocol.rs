@@ -0,0 +1,110 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    player::FpsController,
+    preset::MovementPreset,
+    replay::InputFrame,
+    spawn::{reset_controller, ControllerReset},
+};
+
+/// Bump whenever a message changes shape, mismatched clients are refused.
+pub const PROTOCOL_VERSION: u32 = 3;
+/// How many of the latest inputs go out with every packet, so a few lost ones don't stall the
+/// server.
+pub const INPUT_REDUNDANCY: usize = 8;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum ClientMessage {
+    /// Sent until a [`ServerMessage::Welcome`] arrives
+    Hello {
+        version: u32,
+    },
+    /// Inputs for consecutive ticks, the last one being for `tick`
+    Input {
+        tick: u32,
+        frames: Vec<InputFrame>,
+    },
+    /// A hitscan shot, aimed at remote players as drawn at server tick `view_tick`
+    Fire {
+        view_tick: f32,
+        direction: Vec3,
+    },
+    Disconnect,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum ServerMessage {
+    Welcome {
+        id: u32,
+    },
+    /// Every player after server tick `tick`, `ack` is the last of the recipient's input ticks
+    /// that went into it and `tuning` is what the server simulates the recipient with. Clients
+    /// load `level` when they aren't on it
+    Snapshot {
+        tick: u32,
+        ack: u32,
+        players: Vec<PlayerState>,
+        tuning: MovementPreset,
+        level: String,
+    },
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct PlayerState {
+    pub id: u32,
+    pub position: Vec3,
+    pub velocity: Vec3,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub height: f32,
+    pub ground_tick: u8,
+    pub has_traction: bool,
+    /// Waiting to respawn, only the server knows, [`PlayerState::capture`] leaves it unset
+    pub dead: bool,
+}
+
+impl PlayerState {
+    pub fn capture(
+        id: u32,
+        controller: &FpsController,
+        transform: &Transform,
+        velocity: &Velocity,
+    ) -> Self {
+        Self {
+            id,
+            position: transform.translation,
+            velocity: velocity.linvel,
+            yaw: controller.yaw,
+            pitch: controller.pitch,
+            height: controller.height,
+            ground_tick: controller.ground_tick,
+            has_traction: controller.has_traction,
+            dead: false,
+        }
+    }
+
+    /// Puts a player back into this state, keeping where it looks.
+    pub fn apply(
+        &self,
+        controller: &mut FpsController,
+        collider: &mut Collider,
+        transform: &mut Transform,
+        velocity: &mut Velocity,
+    ) {
+        reset_controller(
+            ControllerReset {
+                translation: self.position,
+                velocity: self.velocity,
+                height: self.height,
+                ground_tick: self.ground_tick,
+                has_traction: self.has_traction,
+            },
+            controller,
+            collider,
+            transform,
+            velocity,
+        );
+    }
+}
@@ -0,0 +1,335 @@
+use std::{collections::VecDeque, io, net::SocketAddr};
+
+use bevy::{prelude::*, time::Real, utils::HashMap};
+use bevy_rapier3d::prelude::*;
+
+use super::{
+    lag_compensation::{LagCompensation, Shot},
+    model_scale,
+    protocol::{ClientMessage, PlayerState, ServerMessage, PROTOCOL_VERSION},
+    transport::Transport,
+    PlayerModel, RemotePlayer, RemotePlayerModel, TIMEOUT,
+};
+use crate::{
+    health::{Dead, Health},
+    level::{MainScene, SceneStatus},
+    player::{player_body, FpsController, FpsControllerInput, LogicalPlayer},
+    preset::MovementPreset,
+    replay::InputFrame,
+    spawn::{reset_controller, ControllerReset, Enemy, SpawnPoints, SpawnPolicy},
+};
+
+/// The local player's id on a listen server.
+const HOST_ID: u32 = 0;
+/// Inputs queued beyond this many ticks are dropped so a client that got ahead catches up.
+const MAX_QUEUED_INPUTS: usize = 4;
+
+pub struct ClientConnection {
+    pub id: u32,
+    pub entity: Entity,
+    /// Received inputs not simulated yet, by client tick
+    pub inputs: VecDeque<(u32, InputFrame)>,
+    pub last_received: u32,
+    /// Client tick of the last input simulated
+    pub ack: u32,
+    /// [`Time<Real>`] elapsed seconds when the client was last heard from
+    pub last_heard: f32,
+}
+
+#[derive(Resource)]
+pub struct NetServer {
+    pub transport: Transport,
+    pub clients: HashMap<SocketAddr, ClientConnection>,
+    pub next_id: u32,
+    pub tick: u32,
+}
+
+impl NetServer {
+    pub fn bind(port: u16) -> io::Result<Self> {
+        Ok(Self {
+            transport: Transport::bind(("0.0.0.0", port))?,
+            clients: HashMap::new(),
+            next_id: HOST_ID + 1,
+            tick: 0,
+        })
+    }
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub fn server_receive(
+    mut commands: Commands,
+    time: Res<Time<Real>>,
+    model: Res<PlayerModel>,
+    spawn_points: Res<SpawnPoints>,
+    spawn_policy: Res<SpawnPolicy>,
+    mut server: ResMut<NetServer>,
+    mut lag_compensation: ResMut<LagCompensation>,
+    player_query: Query<&GlobalTransform, Or<(With<LogicalPlayer>, With<RemotePlayer>)>>,
+) {
+    let now = time.elapsed_seconds();
+    let server = &mut *server;
+
+    while let Some((message, from)) = server.transport.receive::<ClientMessage>() {
+        if let Some(client) = server.clients.get_mut(&from) {
+            client.last_heard = now;
+        }
+
+        match message {
+            ClientMessage::Hello { version } if version != PROTOCOL_VERSION => {
+                warn!("Refusing {from}, protocol version {version}");
+            }
+            ClientMessage::Hello { .. } => {
+                if let Some(client) = server.clients.get(&from) {
+                    // Our welcome got lost
+                    server
+                        .transport
+                        .send(&ServerMessage::Welcome { id: client.id }, from);
+                    continue;
+                }
+
+                let id = server.next_id;
+                server.next_id += 1;
+                let players: Vec<Vec3> = player_query.iter().map(|t| t.translation()).collect();
+                let spawn_point = spawn_points.select(*spawn_policy, &players);
+                let controller = FpsController {
+                    enable_input: false,
+                    ..default()
+                };
+                let entity = commands
+                    .spawn((
+                        player_body(
+                            controller.upright_height,
+                            spawn_point.player_translation(controller.upright_height),
+                        ),
+                        FpsControllerInput {
+                            pitch: spawn_point.pitch,
+                            yaw: spawn_point.yaw,
+                            ..default()
+                        },
+                        controller,
+                        Health::new(100.0),
+                        VisibilityBundle::default(),
+                        RemotePlayer { id },
+                        Enemy,
+                    ))
+                    .with_children(|player| {
+                        player.spawn((model.bundle(), RemotePlayerModel));
+                    })
+                    .id();
+                server.clients.insert(
+                    from,
+                    ClientConnection {
+                        id,
+                        entity,
+                        inputs: VecDeque::new(),
+                        last_received: 0,
+                        ack: 0,
+                        last_heard: now,
+                    },
+                );
+                server.transport.send(&ServerMessage::Welcome { id }, from);
+                info!("Player {id} joined from {from}");
+            }
+            ClientMessage::Input { tick, frames } => {
+                let Some(client) = server.clients.get_mut(&from) else {
+                    continue;
+                };
+                let first_tick = (tick + 1).saturating_sub(frames.len() as u32);
+                for (frame_tick, frame) in (first_tick..).zip(frames) {
+                    if frame_tick > client.last_received {
+                        client.inputs.push_back((frame_tick, frame));
+                        client.last_received = frame_tick;
+                    }
+                }
+            }
+            ClientMessage::Fire {
+                view_tick,
+                direction,
+            } => {
+                if let Some(client) = server.clients.get(&from) {
+                    lag_compensation.shots.push(Shot {
+                        shooter: client.entity,
+                        view_tick,
+                        direction,
+                    });
+                }
+            }
+            ClientMessage::Disconnect => {
+                if let Some(client) = server.clients.remove(&from) {
+                    commands.entity(client.entity).despawn_recursive();
+                    info!("Player {} left", client.id);
+                }
+            }
+        }
+    }
+}
+
+pub fn server_timeouts(
+    mut commands: Commands,
+    time: Res<Time<Real>>,
+    mut server: ResMut<NetServer>,
+) {
+    let now = time.elapsed_seconds();
+    server.clients.retain(|_, client| {
+        let alive = now - client.last_heard < TIMEOUT;
+        if !alive {
+            commands.entity(client.entity).despawn_recursive();
+            info!("Player {} timed out", client.id);
+        }
+        alive
+    });
+}
+
+/// Sends every player's state after the last tick, before this tick's inputs are applied.
+#[allow(clippy::type_complexity)]
+pub fn server_send_snapshots(
+    main_scene: Res<MainScene>,
+    mut server: ResMut<NetServer>,
+    host_query: Query<(&FpsController, &Transform, &Velocity, Has<Dead>), With<LogicalPlayer>>,
+    remote_query: Query<(
+        &RemotePlayer,
+        &FpsController,
+        &Transform,
+        &Velocity,
+        &Children,
+        Has<Dead>,
+    )>,
+    mut model_query: Query<
+        (&mut Transform, &mut Visibility),
+        (With<RemotePlayerModel>, Without<RemotePlayer>),
+    >,
+) {
+    let mut players = Vec::new();
+    if let Ok((controller, transform, velocity, dead)) = host_query.get_single() {
+        players.push(PlayerState {
+            dead,
+            ..PlayerState::capture(HOST_ID, controller, transform, velocity)
+        });
+    }
+    for (remote, controller, transform, velocity, children, dead) in &remote_query {
+        players.push(PlayerState {
+            dead,
+            ..PlayerState::capture(remote.id, controller, transform, velocity)
+        });
+        for &child in children {
+            if let Ok((mut model, mut visibility)) = model_query.get_mut(child) {
+                model.scale = model_scale(controller.height);
+                *visibility = if dead {
+                    Visibility::Hidden
+                } else {
+                    Visibility::Inherited
+                };
+            }
+        }
+    }
+
+    let server = &mut *server;
+    for (&address, client) in &server.clients {
+        let tuning = match remote_query.get(client.entity) {
+            Ok((_, controller, ..)) => MovementPreset::capture(controller),
+            Err(_) => MovementPreset::default(),
+        };
+        let snapshot = ServerMessage::Snapshot {
+            tick: server.tick,
+            ack: client.ack,
+            players: players.clone(),
+            tuning,
+            level: main_scene.level.clone(),
+        };
+        server.transport.send(&snapshot, address);
+    }
+}
+
+/// Feeds each client's next input into its player, repeating the last one when none arrived
+/// in time.
+pub fn server_apply_inputs(
+    mut server: ResMut<NetServer>,
+    mut input_query: Query<&mut FpsControllerInput, (With<RemotePlayer>, Without<Dead>)>,
+) {
+    server.tick += 1;
+    for client in server.clients.values_mut() {
+        while client.inputs.len() > MAX_QUEUED_INPUTS {
+            client.inputs.pop_front();
+        }
+        let Some((tick, frame)) = client.inputs.pop_front() else {
+            continue;
+        };
+        if let Ok(mut input) = input_query.get_mut(client.entity) {
+            frame.apply(&mut input);
+        }
+        client.ack = tick;
+    }
+}
+
+/// Brings dead remote players back at a spawn point, like the local player's respawn, and
+/// starts everyone over on a spawn when a different level has loaded.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub fn server_respawn_players(
+    mut commands: Commands,
+    time: Res<Time>,
+    main_scene: Res<MainScene>,
+    spawn_points: Res<SpawnPoints>,
+    spawn_policy: Res<SpawnPolicy>,
+    mut spawned_level: Local<String>,
+    mut query: Query<
+        (
+            Entity,
+            Option<&mut Dead>,
+            &mut Health,
+            &mut FpsController,
+            &mut FpsControllerInput,
+            &mut Collider,
+            &mut Transform,
+            &mut Velocity,
+        ),
+        With<RemotePlayer>,
+    >,
+    player_query: Query<(Entity, &GlobalTransform), Or<(With<LogicalPlayer>, With<RemotePlayer>)>>,
+) {
+    let level_changed =
+        main_scene.status == SceneStatus::Loaded && *spawned_level != main_scene.level;
+    if level_changed {
+        spawned_level.clone_from(&main_scene.level);
+    }
+
+    for (
+        entity,
+        dead,
+        mut health,
+        mut controller,
+        mut input,
+        mut collider,
+        mut transform,
+        mut velocity,
+    ) in &mut query
+    {
+        let respawn_due = match dead {
+            Some(mut dead) => dead.respawn_timer.tick(time.delta()).finished(),
+            None => false,
+        };
+        if !respawn_due && !level_changed {
+            continue;
+        }
+
+        let others: Vec<Vec3> = player_query
+            .iter()
+            .filter(|(other, _)| *other != entity)
+            .map(|(_, t)| t.translation())
+            .collect();
+        let spawn_point = spawn_points.select(*spawn_policy, &others);
+        health.current = health.max;
+        reset_controller(
+            ControllerReset::spawn(&spawn_point, &controller),
+            &mut controller,
+            &mut collider,
+            &mut transform,
+            &mut velocity,
+        );
+        *input = FpsControllerInput {
+            yaw: spawn_point.yaw,
+            pitch: spawn_point.pitch,
+            ..default()
+        };
+        commands.entity(entity).remove::<Dead>();
+    }
+}
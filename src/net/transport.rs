@@ -0,0 +1,111 @@
+use std::{
+    io,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+};
+
+use bevy::prelude::*;
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::conditioner::{DelayQueue, LinkConditioner};
+
+/// Big enough for a snapshot of a full server without fragmenting.
+const MAX_PACKET_SIZE: usize = 1400;
+
+/// Non-blocking UDP socket sending and receiving bincode messages.
+pub struct Transport {
+    socket: UdpSocket,
+    buffer: [u8; MAX_PACKET_SIZE],
+    conditioner: LinkConditioner,
+    outgoing: DelayQueue,
+    incoming: DelayQueue,
+}
+
+impl Transport {
+    pub fn bind(address: impl ToSocketAddrs) -> io::Result<Self> {
+        let socket = UdpSocket::bind(address)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            socket,
+            buffer: [0; MAX_PACKET_SIZE],
+            conditioner: LinkConditioner::default(),
+            outgoing: DelayQueue::default(),
+            incoming: DelayQueue::default(),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Packets already held back still go out with the conditions they were sent under.
+    pub fn set_conditioner(&mut self, conditioner: LinkConditioner) {
+        self.conditioner = conditioner;
+    }
+
+    pub fn send(&mut self, message: &impl Serialize, to: SocketAddr) {
+        let bytes = match bincode::serialize(message) {
+            Ok(bytes) => bytes,
+            Err(error) => {
+                warn!("Couldn't encode packet: {error}");
+                return;
+            }
+        };
+        if self.conditioner.is_enabled() {
+            self.outgoing.push(&self.conditioner, bytes, to);
+        } else {
+            self.send_bytes(&bytes, to);
+        }
+        self.flush();
+    }
+
+    /// Sends the held back packets that are due.
+    fn flush(&mut self) {
+        while let Some((bytes, to)) = self.outgoing.pop() {
+            self.send_bytes(&bytes, to);
+        }
+    }
+
+    fn send_bytes(&self, bytes: &[u8], to: SocketAddr) {
+        if let Err(error) = self.socket.send_to(bytes, to) {
+            if error.kind() != io::ErrorKind::WouldBlock {
+                warn!("Couldn't send packet to {to}: {error}");
+            }
+        }
+    }
+
+    /// The next well formed message waiting on the socket, garbage is dropped.
+    pub fn receive<T: DeserializeOwned>(&mut self) -> Option<(T, SocketAddr)> {
+        self.flush();
+        loop {
+            let (bytes, from) = self.next_packet()?;
+            if let Ok(message) = bincode::deserialize(&bytes) {
+                return Some((message, from));
+            }
+        }
+    }
+
+    fn next_packet(&mut self) -> Option<(Vec<u8>, SocketAddr)> {
+        if !self.conditioner.is_enabled() && self.incoming.is_empty() {
+            return self.receive_bytes();
+        }
+        while let Some((bytes, from)) = self.receive_bytes() {
+            self.incoming.push(&self.conditioner, bytes, from);
+        }
+        self.incoming.pop()
+    }
+
+    fn receive_bytes(&mut self) -> Option<(Vec<u8>, SocketAddr)> {
+        loop {
+            match self.socket.recv_from(&mut self.buffer) {
+                Ok((length, from)) => return Some((self.buffer[..length].to_vec(), from)),
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => return None,
+                // Windows reports an earlier send to a closed port on the next receive
+                Err(error) if error.kind() == io::ErrorKind::ConnectionReset => {}
+                Err(error) => {
+                    warn!("Couldn't receive packet: {error}");
+                    return None;
+                }
+            }
+        }
+    }
+}
@@ -3,6 +3,8 @@ use std::f32::consts::*;
 use bevy::{input::mouse::MouseMotion, math::Vec3Swizzles, prelude::*};
 use bevy_rapier3d::prelude::*;
 
+use crate::{preset::MovementPreset, state::scene_ready};
+
 pub struct FpsControllerPlugin;
 
 impl Plugin for FpsControllerPlugin {
@@ -11,12 +13,7 @@ impl Plugin for FpsControllerPlugin {
 
         app.add_systems(
             PreUpdate,
-            (
-                fps_controller_input,
-                fps_controller_look,
-                fps_controller_move,
-                fps_controller_render,
-            )
+            (fps_controller_input, fps_controller_look)
                 .chain()
                 .after(mouse::mouse_button_input_system)
                 .after(keyboard::keyboard_input_system)
@@ -24,8 +21,13 @@ impl Plugin for FpsControllerPlugin {
                 .after(gamepad::gamepad_button_event_system)
                 .after(gamepad::gamepad_connection_system)
                 .after(gamepad::gamepad_event_system)
-                .after(touch::touch_screen_input_system),
-        );
+                .after(touch::touch_screen_input_system)
+                .run_if(scene_ready),
+        )
+        // Moving on the fixed timestep, together with physics, makes every tick reproducible
+        .add_systems(FixedFirst, fps_controller_record_translation)
+        .add_systems(FixedUpdate, fps_controller_move.run_if(scene_ready))
+        .add_systems(Update, fps_controller_render);
     }
 }
 
@@ -49,6 +51,8 @@ pub struct FpsControllerInput {
     pub pitch: f32,
     pub yaw: f32,
     pub movement: Vec3,
+    /// Mouse motion applied to pitch and yaw this frame
+    pub look_delta: Vec2,
 }
 
 #[derive(Component)]
@@ -74,8 +78,11 @@ pub struct FpsController {
     pub pitch: f32,
     pub yaw: f32,
     pub ground_tick: u8,
+    /// Standing on something flat enough to walk on, as of the last move
+    pub has_traction: bool,
     pub stop_speed: f32,
     pub sensitivity: f32,
+    pub invert_y: bool,
     pub enable_input: bool,
     pub step_offset: f32,
     pub key_forward: KeyCode,
@@ -87,31 +94,34 @@ pub struct FpsController {
 }
 
 impl Default for FpsController {
+    /// Tuned by [`MovementPreset::default`], the active preset replaces it once loaded.
     fn default() -> Self {
+        let preset = MovementPreset::default();
         Self {
-            gravity: 23.0,
-            walk_speed: 9.0,
-            forward_speed: 30.0,
-            side_speed: 30.0,
-            air_speed_cap: 2.0,
-            air_acceleration: 20.0,
-            max_air_speed: 15.0,
-            crouched_speed: 5.0,
-            crouch_speed: 6.0,
-            uncrouch_speed: 8.0,
-            height: 3.0,
-            upright_height: 3.0,
-            crouch_height: 2.0,
-            acceleration: 10.0,
-            friction: 10.0,
-            traction_normal_cutoff: 0.7,
-            friction_speed_cutoff: 0.1,
+            gravity: preset.gravity,
+            walk_speed: preset.walk_speed,
+            forward_speed: preset.forward_speed,
+            side_speed: preset.side_speed,
+            air_speed_cap: preset.air_speed_cap,
+            air_acceleration: preset.air_acceleration,
+            max_air_speed: preset.max_air_speed,
+            crouched_speed: preset.crouched_speed,
+            crouch_speed: preset.crouch_speed,
+            uncrouch_speed: preset.uncrouch_speed,
+            height: preset.upright_height,
+            upright_height: preset.upright_height,
+            crouch_height: preset.crouch_height,
+            acceleration: preset.acceleration,
+            friction: preset.friction,
+            traction_normal_cutoff: preset.traction_normal_cutoff,
+            friction_speed_cutoff: preset.friction_speed_cutoff,
             pitch: 0.0,
             yaw: 0.0,
             ground_tick: 0,
-            stop_speed: 1.0,
-            jump_speed: 8.5,
-            step_offset: 0.25,
+            has_traction: false,
+            stop_speed: preset.stop_speed,
+            jump_speed: preset.jump_speed,
+            step_offset: preset.step_offset,
             enable_input: true,
             key_forward: KeyCode::KeyW,
             key_back: KeyCode::KeyS,
@@ -120,10 +130,97 @@ impl Default for FpsController {
             key_jump: KeyCode::Space,
             key_crouch: KeyCode::ShiftLeft,
             sensitivity: 0.001,
+            invert_y: false,
         }
     }
 }
 
+/// Shape casts made by [`fps_controller_move`] on its last run, only recorded for
+/// controllers that have this component.
+#[derive(Component, Default)]
+pub struct ControllerDebug {
+    pub casts: Vec<ControllerCast>,
+}
+
+pub struct ControllerCast {
+    pub shape: Collider,
+    pub origin: Vec3,
+    pub rotation: Quat,
+    /// Where the shape would end up if it hit nothing
+    pub end: Vec3,
+    pub hit: Option<ControllerCastHit>,
+}
+
+/// World space contact, `position` is where the cast shape was stopped.
+pub struct ControllerCastHit {
+    pub position: Vec3,
+    pub point: Vec3,
+    pub normal: Vec3,
+}
+
+impl ControllerDebug {
+    fn record(
+        &mut self,
+        shape: &Collider,
+        origin: Vec3,
+        rotation: Quat,
+        direction: Vec3,
+        max_time_of_impact: f32,
+        cast: Option<(Entity, ShapeCastHit)>,
+    ) {
+        let hit = unwrap_hit_details(cast).map(|(hit, details)| ControllerCastHit {
+            position: origin + direction * hit.time_of_impact,
+            point: details.witness1,
+            normal: details.normal1,
+        });
+        self.casts.push(ControllerCast {
+            shape: shape.clone(),
+            origin,
+            rotation,
+            end: origin + direction * max_time_of_impact,
+            hit,
+        });
+    }
+}
+
+/// Physics components every controller needs, a `height` tall cylinder centered on `translation`.
+pub fn player_body(height: f32, translation: Vec3) -> impl Bundle {
+    (
+        Collider::cylinder(height / 2.0, 0.5),
+        Friction {
+            coefficient: 0.0,
+            combine_rule: CoefficientCombineRule::Min,
+        },
+        Restitution {
+            coefficient: 0.0,
+            combine_rule: CoefficientCombineRule::Min,
+        },
+        ActiveEvents::COLLISION_EVENTS,
+        Velocity::zero(),
+        RigidBody::Dynamic,
+        Sleeping::disabled(),
+        LockedAxes::ROTATION_LOCKED,
+        AdditionalMassProperties::Mass(1.0),
+        GravityScale(0.0),
+        Ccd { enabled: true }, // Prevent clipping when going fast
+        TransformBundle::from_transform(Transform::from_translation(translation)),
+        PreviousTranslation(translation),
+    )
+}
+
+/// Translation before the latest fixed tick, so rendering can interpolate towards the current one.
+#[derive(Component, Default)]
+pub struct PreviousTranslation(pub Vec3);
+
+/// Moves further than this in one tick are teleports and aren't interpolated.
+const MAX_INTERPOLATED_DISTANCE: f32 = 4.0;
+
+pub fn fps_controller_record_translation(mut query: Query<(&Transform, &mut PreviousTranslation)>) {
+    for (transform, mut previous) in &mut query {
+        previous.0 = transform.translation;
+    }
+}
+
 const ANGLE_EPSILON: f32 = 0.001953125;
 const GROUNDED_DISTANCE: f32 = 0.125;
 const SLIGHT_SCALE_DOWN: f32 = 0.9375;
@@ -142,6 +239,10 @@ pub fn fps_controller_input(
             mouse_delta += mouse_event.delta;
         }
         mouse_delta *= controller.sensitivity;
+        if controller.invert_y {
+            mouse_delta.y = -mouse_delta.y;
+        }
+        input.look_delta = mouse_delta;
 
         input.pitch = (input.pitch - mouse_delta.y)
             .clamp(-FRAC_PI_2 + ANGLE_EPSILON, FRAC_PI_2 - ANGLE_EPSILON);
@@ -167,6 +268,7 @@ pub fn fps_controller_look(mut query: Query<(&mut FpsController, &FpsControllerI
     }
 }
 
+#[allow(clippy::type_complexity)]
 pub fn fps_controller_move(
     time: Res<Time>,
     physics_context: Res<RapierContext>,
@@ -177,22 +279,35 @@ pub fn fps_controller_move(
         &mut Collider,
         &mut Transform,
         &mut Velocity,
+        Option<&mut ControllerDebug>,
     )>,
 ) {
     let dt = time.delta_seconds();
 
-    for (entity, input, mut controller, mut collider, mut transform, mut velocity) in
+    for (entity, input, mut controller, mut collider, mut transform, mut velocity, mut debug) in
         query.iter_mut()
     {
         let filter = QueryFilter::default().exclude_rigid_body(entity);
+        let ground_shape = scaled_collider_laterally(&collider, SLIGHT_SCALE_DOWN);
         let ground_cast = physics_context.cast_shape(
             transform.translation,
             transform.rotation,
             -Vec3::Y,
-            &scaled_collider_laterally(&collider, SLIGHT_SCALE_DOWN),
+            &ground_shape,
             ShapeCastOptions::with_max_time_of_impact(GROUNDED_DISTANCE),
             filter,
         );
+        if let Some(debug) = debug.as_deref_mut() {
+            debug.casts.clear();
+            debug.record(
+                &ground_shape,
+                transform.translation,
+                transform.rotation,
+                -Vec3::Y,
+                GROUNDED_DISTANCE,
+                ground_cast,
+            );
+        }
 
         let speeds = Vec3::new(controller.side_speed, 0.0, controller.forward_speed);
         let mut move_to_world = Mat3::from_axis_angle(Vec3::Y, input.yaw);
@@ -213,6 +328,7 @@ pub fn fps_controller_move(
         if let Some((hit, hit_details)) = unwrap_hit_details(ground_cast) {
             let has_traction =
                 Vec3::dot(hit_details.normal1, Vec3::Y) > controller.traction_normal_cutoff;
+            controller.has_traction = has_traction;
 
             if controller.ground_tick >= 1 && has_traction {
                 let lateral_speed = velocity.linvel.xz().length();
@@ -255,6 +371,7 @@ pub fn fps_controller_move(
             controller.ground_tick = controller.ground_tick.saturating_add(1);
         } else {
             controller.ground_tick = 0;
+            controller.has_traction = false;
             wish_speed = f32::min(wish_speed, controller.air_speed_cap);
 
             let mut add = acceleration(
@@ -288,15 +405,7 @@ pub fn fps_controller_move(
         controller.height += dt * crouch_speed;
         controller.height = controller.height.clamp(crouch_height, upright_height);
 
-        if let Some(mut capsule) = collider.as_capsule_mut() {
-            let radius = capsule.radius();
-            let half = Vec3::Y * (controller.height * 0.5 - radius);
-            capsule.set_segment(-half, half);
-        } else if let Some(mut cylinder) = collider.as_cylinder_mut() {
-            cylinder.set_half_height(controller.height * 0.5);
-        } else {
-            panic!("Controller must use a cylinder or capsule collider")
-        }
+        set_collider_height(&mut collider, controller.height);
 
         if collider.as_cylinder().is_some()
             && controller.step_offset > f32::EPSILON
@@ -314,6 +423,16 @@ pub fn fps_controller_move(
                 ),
                 filter,
             );
+            if let Some(debug) = debug.as_deref_mut() {
+                debug.record(
+                    &collider,
+                    future_position_lifted,
+                    transform.rotation,
+                    -Vec3::Y,
+                    controller.step_offset * SLIGHT_SCALE_DOWN,
+                    cast,
+                );
+            }
             if let Some((hit, details)) = unwrap_hit_details(cast) {
                 let has_traction_on_ledge =
                     Vec3::dot(details.normal1, Vec3::Y) > controller.traction_normal_cutoff;
@@ -332,6 +451,7 @@ pub fn fps_controller_move(
                     physics_context.as_ref(),
                     velocity.linvel,
                     dt,
+                    debug.as_deref_mut(),
                 );
                 if let Some(overhang) = overhang {
                     velocity.linvel -= overhang;
@@ -345,6 +465,7 @@ pub fn fps_controller_move(
                 physics_context.as_ref(),
                 velocity.linvel,
                 dt,
+                debug.as_deref_mut(),
             )
             .is_some()
             {
@@ -376,6 +497,18 @@ fn collider_y_offset(collider: &Collider) -> Vec3 {
         }
 }
 
+pub fn set_collider_height(collider: &mut Collider, height: f32) {
+    if let Some(mut capsule) = collider.as_capsule_mut() {
+        let radius = capsule.radius();
+        let half = Vec3::Y * (height * 0.5 - radius);
+        capsule.set_segment(-half, half);
+    } else if let Some(mut cylinder) = collider.as_cylinder_mut() {
+        cylinder.set_half_height(height * 0.5);
+    } else {
+        panic!("Controller must use a cylinder or capsule collider")
+    }
+}
+
 fn scaled_collider_laterally(collider: &Collider, scale: f32) -> Collider {
     if let Some(cylinder) = collider.as_cylinder() {
         let new_cylinder = Collider::cylinder(cylinder.half_height(), cylinder.radius() * scale);
@@ -399,6 +532,7 @@ fn overhang_component(
     physics_context: &RapierContext,
     velocity: Vec3,
     dt: f32,
+    debug: Option<&mut ControllerDebug>,
 ) -> Option<Vec3> {
     let cast_capsule = Collider::capsule(Vec3::Y * 0.25, -Vec3::Y * 0.25, 0.01);
     let filter = QueryFilter::default().exclude_rigid_body(entity);
@@ -412,6 +546,16 @@ fn overhang_component(
         ShapeCastOptions::with_max_time_of_impact(0.5),
         filter,
     );
+    if let Some(debug) = debug {
+        debug.record(
+            &cast_capsule,
+            future_position,
+            transform.rotation,
+            -velocity,
+            0.5,
+            cast,
+        );
+    }
     if let Some((_, hit_details)) = unwrap_hit_details(cast) {
         let cast = physics_context.cast_ray(
             future_position + Vec3::Y * 0.125,
@@ -459,21 +603,39 @@ fn get_axis(key_input: &Res<ButtonInput<KeyCode>>, key_pos: KeyCode, key_neg: Ke
     get_pressed(key_input, key_pos) - get_pressed(key_input, key_neg)
 }
 
+/// Places the camera between the last two fixed ticks, by how far into the next tick we are.
+#[allow(clippy::type_complexity)]
 pub fn fps_controller_render(
+    fixed_time: Res<Time<Fixed>>,
     mut render_query: Query<(&mut Transform, &RenderPlayer), With<RenderPlayer>>,
     logical_query: Query<
-        (&Transform, &Collider, &FpsController, &CameraConfig),
+        (
+            &Transform,
+            Option<&PreviousTranslation>,
+            &Collider,
+            &FpsController,
+            &CameraConfig,
+        ),
         (With<LogicalPlayer>, Without<RenderPlayer>),
     >,
 ) {
+    let fraction = fixed_time.overstep_fraction();
     for (mut render_transform, render_player) in render_query.iter_mut() {
-        if let Ok((logical_transform, collider, controller, camera_config)) =
+        if let Ok((logical_transform, previous, collider, controller, camera_config)) =
             logical_query.get(render_player.logical_entity)
         {
+            let current = logical_transform.translation;
+            let translation = match previous {
+                Some(&PreviousTranslation(previous))
+                    if previous.distance(current) < MAX_INTERPOLATED_DISTANCE =>
+                {
+                    previous.lerp(current, fraction)
+                }
+                _ => current,
+            };
             let collider_offset = collider_y_offset(collider);
             let camera_offset = Vec3::Y * camera_config.height_offset;
-            render_transform.translation =
-                logical_transform.translation + collider_offset + camera_offset;
+            render_transform.translation = translation + collider_offset + camera_offset;
             render_transform.rotation =
                 Quat::from_euler(EulerRot::YXZ, controller.yaw, controller.pitch, 0.0);
         }
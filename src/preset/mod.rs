@@ -0,0 +1,257 @@
+use std::{fmt, io};
+
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::player::FpsController;
+
+/// Presets shipped in `assets/presets`, cycled through in this order.
+pub const PRESETS: [&str; 4] = ["source", "quake", "arcade", "realistic"];
+
+/// Loads [`MovementPreset`]s from `assets/presets/<name>.preset.ron` and applies the active one
+/// to every controller, again whenever its file changes. F5 cycles through [`PRESETS`].
+pub struct MovementPresetPlugin;
+
+impl Plugin for MovementPresetPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<MovementPreset>()
+            .register_asset_loader(MovementPresetLoader)
+            .init_resource::<ActivePreset>()
+            .add_systems(
+                Update,
+                (
+                    cycle_preset_input,
+                    load_active_preset,
+                    apply_movement_preset,
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// The tuning half of an [`FpsController`], everything but its state and key bindings.
+#[derive(Asset, TypePath, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MovementPreset {
+    pub gravity: f32,
+    pub walk_speed: f32,
+    pub forward_speed: f32,
+    pub side_speed: f32,
+    pub air_speed_cap: f32,
+    pub air_acceleration: f32,
+    pub max_air_speed: f32,
+    pub acceleration: f32,
+    pub friction: f32,
+    pub traction_normal_cutoff: f32,
+    pub friction_speed_cutoff: f32,
+    pub jump_speed: f32,
+    pub crouched_speed: f32,
+    pub crouch_speed: f32,
+    pub uncrouch_speed: f32,
+    pub upright_height: f32,
+    pub crouch_height: f32,
+    pub stop_speed: f32,
+    pub step_offset: f32,
+}
+
+impl Default for MovementPreset {
+    /// The source preset, built in so controllers have a tuning before any asset has loaded.
+    fn default() -> Self {
+        ron::de::from_str(include_str!("../../assets/presets/source.preset.ron"))
+            .expect("built in movement preset is valid")
+    }
+}
+
+impl MovementPreset {
+    /// The tuning a controller has right now, presets and cvar changes included.
+    pub fn capture(controller: &FpsController) -> Self {
+        Self {
+            gravity: controller.gravity,
+            walk_speed: controller.walk_speed,
+            forward_speed: controller.forward_speed,
+            side_speed: controller.side_speed,
+            air_speed_cap: controller.air_speed_cap,
+            air_acceleration: controller.air_acceleration,
+            max_air_speed: controller.max_air_speed,
+            acceleration: controller.acceleration,
+            friction: controller.friction,
+            traction_normal_cutoff: controller.traction_normal_cutoff,
+            friction_speed_cutoff: controller.friction_speed_cutoff,
+            jump_speed: controller.jump_speed,
+            crouched_speed: controller.crouched_speed,
+            crouch_speed: controller.crouch_speed,
+            uncrouch_speed: controller.uncrouch_speed,
+            upright_height: controller.upright_height,
+            crouch_height: controller.crouch_height,
+            stop_speed: controller.stop_speed,
+            step_offset: controller.step_offset,
+        }
+    }
+
+    pub fn apply(&self, controller: &mut FpsController) {
+        controller.gravity = self.gravity;
+        controller.walk_speed = self.walk_speed;
+        controller.forward_speed = self.forward_speed;
+        controller.side_speed = self.side_speed;
+        controller.air_speed_cap = self.air_speed_cap;
+        controller.air_acceleration = self.air_acceleration;
+        controller.max_air_speed = self.max_air_speed;
+        controller.acceleration = self.acceleration;
+        controller.friction = self.friction;
+        controller.traction_normal_cutoff = self.traction_normal_cutoff;
+        controller.friction_speed_cutoff = self.friction_speed_cutoff;
+        controller.jump_speed = self.jump_speed;
+        controller.crouched_speed = self.crouched_speed;
+        controller.crouch_speed = self.crouch_speed;
+        controller.uncrouch_speed = self.uncrouch_speed;
+        controller.upright_height = self.upright_height;
+        controller.crouch_height = self.crouch_height;
+        controller.stop_speed = self.stop_speed;
+        controller.step_offset = self.step_offset;
+    }
+}
+
+#[derive(Default)]
+pub struct MovementPresetLoader;
+
+#[derive(Debug)]
+pub enum MovementPresetLoaderError {
+    Io(io::Error),
+    Ron(ron::error::SpannedError),
+}
+
+impl fmt::Display for MovementPresetLoaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "couldn't read movement preset: {error}"),
+            Self::Ron(error) => write!(f, "invalid movement preset: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for MovementPresetLoaderError {}
+
+impl From<io::Error> for MovementPresetLoaderError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<ron::error::SpannedError> for MovementPresetLoaderError {
+    fn from(error: ron::error::SpannedError) -> Self {
+        Self::Ron(error)
+    }
+}
+
+impl AssetLoader for MovementPresetLoader {
+    type Asset = MovementPreset;
+    type Settings = ();
+    type Error = MovementPresetLoaderError;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext<'_>,
+    ) -> Result<MovementPreset, MovementPresetLoaderError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        Ok(ron::de::from_bytes(&bytes)?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["preset.ron"]
+    }
+}
+
+/// Which preset controllers use. Changing `name` loads it, any name with a file in
+/// `assets/presets` works, not only [`PRESETS`].
+#[derive(Resource)]
+pub struct ActivePreset {
+    pub name: String,
+    pub handle: Option<Handle<MovementPreset>>,
+    pub cycle_key: KeyCode,
+    /// Why the tuning can't be changed right now, e.g. "while connected". Switching presets,
+    /// the `controller.*` cvars and edits to the preset file are ignored while set.
+    pub locked: Option<&'static str>,
+    /// Tuning used instead of the preset, like the server's while connected.
+    pub tuning_override: Option<MovementPreset>,
+}
+
+impl Default for ActivePreset {
+    fn default() -> Self {
+        Self {
+            name: String::from(PRESETS[0]),
+            handle: None,
+            cycle_key: KeyCode::F5,
+            locked: None,
+            tuning_override: None,
+        }
+    }
+}
+
+pub fn cycle_preset_input(key_input: Res<ButtonInput<KeyCode>>, mut active: ResMut<ActivePreset>) {
+    if !key_input.just_pressed(active.cycle_key) {
+        return;
+    }
+    if let Some(reason) = active.locked {
+        info!("Movement preset can't be changed {reason}");
+        return;
+    }
+    let next = match PRESETS.iter().position(|name| *name == active.name) {
+        Some(index) => PRESETS[(index + 1) % PRESETS.len()],
+        None => PRESETS[0],
+    };
+    active.name = String::from(next);
+    info!("Movement preset {next}");
+}
+
+/// Swaps the handle when the name changes, keeping the previous tuning until the new file
+/// has loaded.
+pub fn load_active_preset(asset_server: Res<AssetServer>, mut active: ResMut<ActivePreset>) {
+    if !active.is_changed() {
+        return;
+    }
+    let path = format!("presets/{}.preset.ron", active.name);
+    let loaded = match &active.handle {
+        Some(handle) => asset_server.get_path(handle.id()),
+        None => None,
+    };
+    if loaded.is_some_and(|loaded| loaded.path().to_str() == Some(path.as_str())) {
+        return;
+    }
+    active.handle = Some(asset_server.load(path));
+}
+
+/// Retunes every controller when the active preset loads or its file changes, and new
+/// controllers as they are spawned.
+pub fn apply_movement_preset(
+    active: Res<ActivePreset>,
+    presets: Res<Assets<MovementPreset>>,
+    mut asset_events: EventReader<AssetEvent<MovementPreset>>,
+    mut controller_query: Query<&mut FpsController>,
+) {
+    let Some(handle) = &active.handle else {
+        return;
+    };
+    let loaded = asset_events.read().any(|event| match event {
+        AssetEvent::LoadedWithDependencies { id } | AssetEvent::Modified { id } => {
+            *id == handle.id()
+        }
+        _ => false,
+    });
+    let Some(preset) = active.tuning_override.as_ref().or(presets.get(handle)) else {
+        return;
+    };
+
+    // A preset loaded earlier doesn't send another event when switched back to
+    let preset_changed = (loaded && active.locked.is_none()) || active.is_changed();
+
+    for mut controller in &mut controller_query {
+        if preset_changed || controller.is_added() {
+            preset.apply(&mut controller);
+        }
+    }
+}
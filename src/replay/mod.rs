@@ -0,0 +1,517 @@
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::{app::FixedMain, prelude::*, window::CursorGrabMode};
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    level::{load_level, scene_colliders, LoadLevel, MainScene, SceneStatus},
+    net::resimulating,
+    player::{fps_controller_move, FpsController, FpsControllerInput, LogicalPlayer},
+    preset::{ActivePreset, MovementPreset},
+    spawn::{reset_controller, respawn_player, ControllerReset},
+};
+
+/// Bump whenever [`Recording`] changes shape.
+const RECORDING_VERSION: u32 = 2;
+/// How far the arrow keys scrub, in seconds.
+const SEEK_STEP: f32 = 5.0;
+
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Replay>()
+            .add_systems(
+                Update,
+                (
+                    replay_controls.before(load_level),
+                    start_pending_replay
+                        .after(scene_colliders)
+                        .after(respawn_player),
+                    seek_replay,
+                    lock_tuning,
+                )
+                    .chain(),
+            )
+            .add_systems(
+                FixedUpdate,
+                (replay_input, record_input.run_if(not(resimulating)))
+                    .chain()
+                    .before(fps_controller_move),
+            );
+
+        let mut args = std::env::args().skip_while(|arg| arg != "--replay").skip(1);
+        if let Some(path) = args.next() {
+            app.add_systems(Startup, move |mut replay: ResMut<Replay>| {
+                replay.load(Path::new(&path));
+            });
+        }
+    }
+}
+
+/// Controller input used on one fixed tick.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct InputFrame {
+    /// Sideways and forward movement, each -1, 0 or 1
+    pub movement: [i8; 2],
+    pub jump: bool,
+    pub crouch: bool,
+    pub pitch: f32,
+    pub yaw: f32,
+}
+
+impl InputFrame {
+    pub fn capture(input: &FpsControllerInput) -> Self {
+        Self {
+            movement: [input.movement.x as i8, input.movement.z as i8],
+            jump: input.jump,
+            crouch: input.crouch,
+            pitch: input.pitch,
+            yaw: input.yaw,
+        }
+    }
+
+    pub fn apply(&self, input: &mut FpsControllerInput) {
+        input.movement = Vec3::new(self.movement[0] as f32, 0.0, self.movement[1] as f32);
+        input.jump = self.jump;
+        input.crouch = self.crouch;
+        input.pitch = self.pitch;
+        input.yaw = self.yaw;
+    }
+}
+
+/// Everything about the player a recording starts from.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct InitialState {
+    pub translation: Vec3,
+    pub velocity: Vec3,
+    pub pitch: f32,
+    pub yaw: f32,
+    pub height: f32,
+    pub ground_tick: u8,
+    pub has_traction: bool,
+    /// Recorded since presets and cvars can change it, and playback depends on it
+    pub tuning: MovementPreset,
+}
+
+impl InitialState {
+    fn capture(controller: &FpsController, transform: &Transform, velocity: &Velocity) -> Self {
+        Self {
+            translation: transform.translation,
+            velocity: velocity.linvel,
+            pitch: controller.pitch,
+            yaw: controller.yaw,
+            height: controller.height,
+            ground_tick: controller.ground_tick,
+            has_traction: controller.has_traction,
+            tuning: MovementPreset::capture(controller),
+        }
+    }
+
+    fn apply(
+        &self,
+        controller: &mut FpsController,
+        input: &mut FpsControllerInput,
+        collider: &mut Collider,
+        transform: &mut Transform,
+        velocity: &mut Velocity,
+    ) {
+        self.tuning.apply(controller);
+        reset_controller(
+            ControllerReset {
+                translation: self.translation,
+                velocity: self.velocity,
+                height: self.height,
+                ground_tick: self.ground_tick,
+                has_traction: self.has_traction,
+            },
+            controller,
+            collider,
+            transform,
+            velocity,
+        );
+        controller.pitch = self.pitch;
+        controller.yaw = self.yaw;
+        *input = FpsControllerInput {
+            pitch: self.pitch,
+            yaw: self.yaw,
+            ..default()
+        };
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Recording {
+    pub version: u32,
+    pub level: String,
+    /// Seconds per tick the inputs were recorded at
+    pub timestep: f32,
+    pub initial: Option<InitialState>,
+    pub frames: Vec<InputFrame>,
+    /// [`position_checksum`] of where the player ended up after the last frame
+    pub final_checksum: u64,
+}
+
+impl Recording {
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, bincode::serialize(self)?)?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let recording: Recording = bincode::deserialize(&fs::read(path)?)?;
+        if recording.version != RECORDING_VERSION {
+            return Err(format!("unsupported recording version {}", recording.version).into());
+        }
+        Ok(recording)
+    }
+}
+
+/// Where F9 saves and F10 plays back.
+pub fn last_recording_path() -> Option<PathBuf> {
+    Some(
+        dirs::data_dir()?
+            .join("source")
+            .join("replays")
+            .join("last.replay"),
+    )
+}
+
+/// FNV-1a over the exact bits of a position, so any physics divergence changes it.
+pub fn position_checksum(position: Vec3) -> u64 {
+    position
+        .to_array()
+        .iter()
+        .flat_map(|axis| axis.to_bits().to_le_bytes())
+        .fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        })
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReplayState {
+    #[default]
+    Idle,
+    Recording,
+    /// Loaded, waiting for its level before playing
+    Pending,
+    Playing,
+}
+
+#[derive(Resource, Default)]
+pub struct Replay {
+    pub state: ReplayState,
+    pub recording: Recording,
+    /// Next frame to play
+    pub tick: usize,
+    /// Frame to jump to before the next update
+    pub seek_to: Option<usize>,
+}
+
+impl Replay {
+    pub fn load(&mut self, path: &Path) {
+        match Recording::load(path) {
+            Ok(recording) => {
+                self.recording = recording;
+                self.state = ReplayState::Pending;
+            }
+            Err(error) => warn!("Couldn't load replay {}: {error}", path.display()),
+        }
+    }
+}
+
+/// F9 starts and stops recording, F10 plays the last recording back or stops playback.
+/// While playing P pauses, `[` and `]` change speed and the arrow keys scrub.
+#[allow(clippy::too_many_arguments)]
+pub fn replay_controls(
+    key_input: Res<ButtonInput<KeyCode>>,
+    main_scene: Res<MainScene>,
+    fixed_time: Res<Time<Fixed>>,
+    mut virtual_time: ResMut<Time<Virtual>>,
+    mut replay: ResMut<Replay>,
+    mut load_level_events: EventWriter<LoadLevel>,
+    mut player_query: Query<(&mut FpsController, &Transform, &Velocity), With<LogicalPlayer>>,
+    window_query: Query<&Window>,
+) {
+    let Ok((mut controller, transform, velocity)) = player_query.get_single_mut() else {
+        return;
+    };
+
+    if key_input.just_pressed(KeyCode::F9) {
+        match replay.state {
+            ReplayState::Idle if main_scene.status == SceneStatus::Loaded => {
+                replay.recording = Recording {
+                    version: RECORDING_VERSION,
+                    level: main_scene.level.clone(),
+                    timestep: fixed_time.timestep().as_secs_f32(),
+                    initial: Some(InitialState::capture(&controller, transform, velocity)),
+                    ..default()
+                };
+                replay.state = ReplayState::Recording;
+                info!("Recording input");
+            }
+            ReplayState::Recording => {
+                replay.recording.final_checksum = position_checksum(transform.translation);
+                replay.state = ReplayState::Idle;
+                if let Some(path) = last_recording_path() {
+                    match replay.recording.save(&path) {
+                        Ok(()) => info!(
+                            "Saved {} ticks to {}",
+                            replay.recording.frames.len(),
+                            path.display()
+                        ),
+                        Err(error) => warn!("Couldn't save replay: {error}"),
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if key_input.just_pressed(KeyCode::F10) {
+        match replay.state {
+            ReplayState::Idle => {
+                if let Some(path) = last_recording_path() {
+                    replay.load(&path);
+                }
+            }
+            ReplayState::Pending | ReplayState::Playing => {
+                replay.state = ReplayState::Idle;
+                controller.enable_input = cursor_grabbed(&window_query);
+                virtual_time.unpause();
+                virtual_time.set_relative_speed(1.0);
+            }
+            ReplayState::Recording => {}
+        }
+    }
+
+    if replay.state == ReplayState::Pending && main_scene.level != replay.recording.level {
+        if main_scene.status != SceneStatus::Loading {
+            load_level_events.send(LoadLevel {
+                id: replay.recording.level.clone(),
+            });
+        }
+        return;
+    }
+    if replay.state != ReplayState::Playing {
+        return;
+    }
+
+    if key_input.just_pressed(KeyCode::KeyP) {
+        if virtual_time.is_paused() {
+            virtual_time.unpause();
+        } else {
+            virtual_time.pause();
+        }
+    }
+    if key_input.just_pressed(KeyCode::BracketLeft) {
+        let speed = virtual_time.relative_speed();
+        virtual_time.set_relative_speed((speed * 0.5).max(0.125));
+    }
+    if key_input.just_pressed(KeyCode::BracketRight) {
+        let speed = virtual_time.relative_speed();
+        virtual_time.set_relative_speed((speed * 2.0).min(8.0));
+    }
+
+    let step = (SEEK_STEP / replay.recording.timestep) as usize;
+    if key_input.just_pressed(KeyCode::ArrowLeft) {
+        replay.seek_to = Some(replay.tick.saturating_sub(step));
+    }
+    if key_input.just_pressed(KeyCode::ArrowRight) {
+        replay.seek_to = Some(replay.tick + step);
+    }
+}
+
+fn cursor_grabbed(window_query: &Query<&Window>) -> bool {
+    window_query
+        .iter()
+        .any(|window| window.cursor.grab_mode != CursorGrabMode::None)
+}
+
+#[allow(clippy::type_complexity)]
+pub fn start_pending_replay(
+    main_scene: Res<MainScene>,
+    fixed_time: Res<Time<Fixed>>,
+    mut replay: ResMut<Replay>,
+    mut player_query: Query<
+        (
+            &mut FpsController,
+            &mut FpsControllerInput,
+            &mut Collider,
+            &mut Transform,
+            &mut Velocity,
+        ),
+        With<LogicalPlayer>,
+    >,
+) {
+    if replay.state != ReplayState::Pending
+        || main_scene.level != replay.recording.level
+        || main_scene.status != SceneStatus::Loaded
+    {
+        return;
+    }
+
+    if (replay.recording.timestep - fixed_time.timestep().as_secs_f32()).abs() > f32::EPSILON {
+        warn!(
+            "Replay was recorded at {}s per tick, can't play it back at {}s",
+            replay.recording.timestep,
+            fixed_time.timestep().as_secs_f32()
+        );
+        replay.state = ReplayState::Idle;
+        return;
+    }
+    let Some(initial) = replay.recording.initial else {
+        replay.state = ReplayState::Idle;
+        return;
+    };
+
+    for (mut controller, mut input, mut collider, mut transform, mut velocity) in &mut player_query
+    {
+        controller.enable_input = false;
+        initial.apply(
+            &mut controller,
+            &mut input,
+            &mut collider,
+            &mut transform,
+            &mut velocity,
+        );
+    }
+    replay.state = ReplayState::Playing;
+    replay.tick = 0;
+    info!("Playing {} ticks", replay.recording.frames.len());
+}
+
+/// Keeps the tuning from changing while recording or playing back, and restores the preset
+/// over the recording's tuning once playback ends.
+pub fn lock_tuning(
+    replay: Res<Replay>,
+    mut active: ResMut<ActivePreset>,
+    mut locked: Local<Option<ReplayState>>,
+) {
+    let reason = match replay.state {
+        ReplayState::Idle => None,
+        ReplayState::Recording => Some("while recording"),
+        ReplayState::Pending | ReplayState::Playing => Some("during replays"),
+    };
+    match (*locked, reason) {
+        // Someone else's lock, like the network's, stays
+        (None, Some(reason)) if active.locked.is_none() => {
+            // Not a change, that would retune the player over the recording's tuning
+            active.bypass_change_detection().locked = Some(reason);
+            *locked = Some(replay.state);
+        }
+        (Some(ReplayState::Recording), None) => {
+            active.bypass_change_detection().locked = None;
+            *locked = None;
+        }
+        (Some(_), None) => {
+            active.locked = None;
+            *locked = None;
+        }
+        _ => {}
+    }
+}
+
+/// Jumps to [`Replay::seek_to`] by replaying from the start, or from the current tick
+/// when seeking forward, running all fixed ticks in between right away.
+pub fn seek_replay(world: &mut World) {
+    let mut replay = world.resource_mut::<Replay>();
+    let Some(target) = replay.seek_to.take() else {
+        return;
+    };
+    if replay.state != ReplayState::Playing {
+        return;
+    }
+    let target = target.min(replay.recording.frames.len());
+
+    if target < replay.tick {
+        replay.tick = 0;
+        let Some(initial) = replay.recording.initial else {
+            return;
+        };
+        let mut query = world.query_filtered::<(
+            &mut FpsController,
+            &mut FpsControllerInput,
+            &mut Collider,
+            &mut Transform,
+            &mut Velocity,
+        ), With<LogicalPlayer>>();
+        for (mut controller, mut input, mut collider, mut transform, mut velocity) in
+            query.iter_mut(world)
+        {
+            initial.apply(
+                &mut controller,
+                &mut input,
+                &mut collider,
+                &mut transform,
+                &mut velocity,
+            );
+        }
+    }
+
+    *world.resource_mut::<Time>() = world.resource::<Time<Fixed>>().as_generic();
+    while world.resource::<Replay>().state == ReplayState::Playing
+        && world.resource::<Replay>().tick < target
+    {
+        world.run_schedule(FixedMain);
+    }
+    *world.resource_mut::<Time>() = world.resource::<Time<Virtual>>().as_generic();
+}
+
+pub fn replay_input(
+    mut replay: ResMut<Replay>,
+    mut virtual_time: ResMut<Time<Virtual>>,
+    mut player_query: Query<
+        (&mut FpsController, &mut FpsControllerInput, &Transform),
+        With<LogicalPlayer>,
+    >,
+    window_query: Query<&Window>,
+) {
+    if replay.state != ReplayState::Playing {
+        return;
+    }
+    let Ok((mut controller, mut input, transform)) = player_query.get_single_mut() else {
+        return;
+    };
+
+    let Some(frame) = replay.recording.frames.get(replay.tick) else {
+        // The physics step after the last frame has run, so this is where the recording ended
+        let checksum = position_checksum(transform.translation);
+        if checksum == replay.recording.final_checksum {
+            info!("Replay finished in sync");
+        } else {
+            warn!(
+                "Replay diverged, ended at {} ({checksum:x}, recorded {:x})",
+                transform.translation, replay.recording.final_checksum
+            );
+        }
+        replay.state = ReplayState::Idle;
+        controller.enable_input = cursor_grabbed(&window_query);
+        virtual_time.unpause();
+        virtual_time.set_relative_speed(1.0);
+        return;
+    };
+
+    frame.apply(&mut input);
+    replay.tick += 1;
+}
+
+pub fn record_input(
+    mut replay: ResMut<Replay>,
+    player_query: Query<&FpsControllerInput, With<LogicalPlayer>>,
+) {
+    if replay.state != ReplayState::Recording {
+        return;
+    }
+    if let Ok(input) = player_query.get_single() {
+        replay.recording.frames.push(InputFrame::capture(input));
+    }
+}
@@ -0,0 +1,219 @@
+use std::{fs, path::PathBuf};
+
+use bevy::{
+    prelude::*,
+    window::{PrimaryWindow, WindowMode, WindowResolution},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    player::{FpsController, LogicalPlayer, RenderPlayer},
+    processing::PostProcessSettings,
+};
+
+/// Applies [`Settings`] whenever they change and saves them to `settings.ron` in the user's
+/// config directory. Insert the resource before adding the plugin to use them for the initial
+/// window, otherwise they are loaded here.
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        // Loaded after DefaultPlugins rather than before the app, so problems reach the log
+        if !app.world().contains_resource::<Settings>() {
+            app.insert_resource(Settings::load());
+        }
+        // The primary window exists by now but isn't opened until the app runs
+        let window_settings = app.world().resource::<Settings>().window.clone();
+        let world = app.world_mut();
+        let mut window_query = world.query_filtered::<&mut Window, With<PrimaryWindow>>();
+        for mut window in window_query.iter_mut(world) {
+            window_settings.apply(&mut window);
+        }
+        app.add_systems(Update, (apply_settings, save_settings));
+    }
+}
+
+#[derive(Resource, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Radians per pixel of mouse movement
+    pub sensitivity: f32,
+    pub invert_y: bool,
+    /// Vertical field of view in degrees
+    pub fov: f32,
+    pub bindings: KeyBindings,
+    pub post_process_intensity: f32,
+    /// Size of the pixelation blocks in pixels
+    pub pixelation: f32,
+    /// From 0 to 1
+    pub volume: f32,
+    pub window: WindowSettings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            sensitivity: 0.001,
+            invert_y: false,
+            fov: 90.0,
+            bindings: KeyBindings::default(),
+            post_process_intensity: 5.0,
+            pixelation: 4.0,
+            volume: 1.0,
+            window: WindowSettings::default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyBindings {
+    pub forward: KeyCode,
+    pub back: KeyCode,
+    pub left: KeyCode,
+    pub right: KeyCode,
+    pub jump: KeyCode,
+    pub crouch: KeyCode,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            forward: KeyCode::KeyW,
+            back: KeyCode::KeyS,
+            left: KeyCode::KeyA,
+            right: KeyCode::KeyD,
+            jump: KeyCode::Space,
+            crouch: KeyCode::ShiftLeft,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WindowSettings {
+    pub mode: WindowMode,
+    pub width: f32,
+    pub height: f32,
+    /// Overrides the monitor's scale factor, large values make for chunky UI
+    pub scale_factor: Option<f32>,
+}
+
+impl Default for WindowSettings {
+    fn default() -> Self {
+        Self {
+            mode: WindowMode::Windowed,
+            width: 1920.0,
+            height: 1080.0,
+            scale_factor: Some(150.0 * 3.0),
+        }
+    }
+}
+
+impl WindowSettings {
+    pub fn apply(&self, window: &mut Window) {
+        window.mode = self.mode;
+        window.resolution = WindowResolution::new(self.width, self.height);
+        window
+            .resolution
+            .set_scale_factor_override(self.scale_factor);
+    }
+}
+
+impl Settings {
+    pub fn path() -> Option<PathBuf> {
+        Some(dirs::config_dir()?.join("source").join("settings.ron"))
+    }
+
+    /// Falls back to the defaults when there is no file yet or it can't be read.
+    pub fn load() -> Self {
+        let Some(contents) = Self::path().and_then(|path| fs::read_to_string(path).ok()) else {
+            return Self::default();
+        };
+        ron::from_str(&contents).unwrap_or_else(|error| {
+            warn!("Ignoring unreadable settings: {error}");
+            Self::default()
+        })
+    }
+
+    pub fn save(&self) {
+        let Some(path) = Self::path() else {
+            warn!("No config directory to save settings in");
+            return;
+        };
+        let result = ron::ser::to_string_pretty(self, default())
+            .map_err(|error| error.to_string())
+            .and_then(|contents| {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent).map_err(|error| error.to_string())?;
+                }
+                fs::write(&path, contents).map_err(|error| error.to_string())
+            });
+        if let Err(error) = result {
+            warn!("Couldn't save settings: {error}");
+        }
+    }
+
+    pub fn apply_to_controller(&self, controller: &mut FpsController) {
+        controller.sensitivity = self.sensitivity;
+        controller.invert_y = self.invert_y;
+        controller.key_forward = self.bindings.forward;
+        controller.key_back = self.bindings.back;
+        controller.key_left = self.bindings.left;
+        controller.key_right = self.bindings.right;
+        controller.key_jump = self.bindings.jump;
+        controller.key_crouch = self.bindings.crouch;
+    }
+}
+
+/// Pushes the settings into everything they cover when they change, and into the player as
+/// soon as it exists.
+#[allow(clippy::type_complexity)]
+pub fn apply_settings(
+    settings: Res<Settings>,
+    mut last_window: Local<Option<WindowSettings>>,
+    mut controller_query: Query<&mut FpsController, With<LogicalPlayer>>,
+    mut projection_query: Query<&mut Projection, With<RenderPlayer>>,
+    mut post_process_query: Query<&mut PostProcessSettings>,
+    mut global_volume: ResMut<GlobalVolume>,
+    mut window_query: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    for mut controller in &mut controller_query {
+        if settings.is_changed() || controller.is_added() {
+            settings.apply_to_controller(&mut controller);
+        }
+    }
+    for mut projection in &mut projection_query {
+        if settings.is_changed() || projection.is_added() {
+            if let Projection::Perspective(perspective) = &mut *projection {
+                perspective.fov = settings.fov.clamp(1.0, 179.0).to_radians();
+            }
+        }
+    }
+    for mut post_process in &mut post_process_query {
+        if settings.is_changed() || post_process.is_added() {
+            post_process.intensity = settings.post_process_intensity;
+            post_process.block_size = settings.pixelation;
+        }
+    }
+    if settings.is_changed() {
+        *global_volume = GlobalVolume::new(settings.volume.max(0.0));
+    }
+
+    // Only touched when the window settings themselves change, so resizing the window by hand
+    // isn't undone by changing the FOV
+    if last_window.as_ref() != Some(&settings.window) {
+        if last_window.is_some() {
+            for mut window in &mut window_query {
+                settings.window.apply(&mut window);
+            }
+        }
+        *last_window = Some(settings.window.clone());
+    }
+}
+
+pub fn save_settings(settings: Res<Settings>) {
+    if settings.is_changed() && !settings.is_added() {
+        settings.save();
+    }
+}
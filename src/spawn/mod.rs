@@ -0,0 +1,208 @@
+use std::f32::consts::TAU;
+
+use bevy::{gltf::GltfNode, prelude::*};
+use bevy_rapier3d::prelude::*;
+use rand::seq::SliceRandom;
+
+use crate::{
+    level::node_extras,
+    player::{set_collider_height, FpsController, FpsControllerInput, LogicalPlayer},
+};
+
+/// Gap left between the floor and the bottom of the player's collider when spawning.
+const SPAWN_CLEARANCE: f32 = 0.125;
+
+pub struct SpawnPlugin;
+
+impl Plugin for SpawnPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<RespawnPlayer>()
+            .init_resource::<SpawnPoints>()
+            .init_resource::<SpawnPolicy>()
+            .add_systems(Update, respawn_player);
+    }
+}
+
+/// Where the player's feet go and which way they look.
+#[derive(Clone, Copy, Debug)]
+pub struct SpawnPoint {
+    pub position: Vec3,
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+impl Default for SpawnPoint {
+    fn default() -> Self {
+        Self {
+            position: Vec3::ZERO,
+            yaw: TAU * 5.0 / 8.0,
+            pitch: -TAU / 12.0,
+        }
+    }
+}
+
+impl SpawnPoint {
+    /// Translation for a player collider of the given height standing on this spawn.
+    pub fn player_translation(&self, height: f32) -> Vec3 {
+        self.position + Vec3::Y * (height * 0.5 + SPAWN_CLEARANCE)
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct SpawnPoints {
+    pub points: Vec<SpawnPoint>,
+    /// Last checkpoint the local player reached, where they respawn instead of `points` until
+    /// another level is loaded
+    pub active: Option<SpawnPoint>,
+}
+
+#[derive(Resource, Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpawnPolicy {
+    #[default]
+    First,
+    Random,
+    FarthestFromEnemies,
+}
+
+/// Puts the [`LogicalPlayer`] back on its last checkpoint, or a spawn picked by the current
+/// [`SpawnPolicy`], with its controller, collider and input as if it had just been created.
+#[derive(Event, Default)]
+pub struct RespawnPlayer;
+
+/// Anything spawns should keep away from under [`SpawnPolicy::FarthestFromEnemies`], like the
+/// other players in a networked game.
+#[derive(Component)]
+pub struct Enemy;
+
+impl SpawnPoints {
+    /// Falls back to [`SpawnPoint::default`] when the level has no authored spawns. Ignores
+    /// [`SpawnPoints::active`], that checkpoint is only the local player's.
+    pub fn select(&self, policy: SpawnPolicy, enemies: &[Vec3]) -> SpawnPoint {
+        let selected = match policy {
+            SpawnPolicy::First => self.points.first(),
+            SpawnPolicy::Random => self.points.choose(&mut rand::thread_rng()),
+            SpawnPolicy::FarthestFromEnemies if enemies.is_empty() => self.points.first(),
+            SpawnPolicy::FarthestFromEnemies => self.points.iter().max_by(|a, b| {
+                let a = nearest_distance_squared(a.position, enemies);
+                let b = nearest_distance_squared(b.position, enemies);
+                a.total_cmp(&b)
+            }),
+        };
+        selected.copied().unwrap_or_default()
+    }
+}
+
+fn nearest_distance_squared(position: Vec3, others: &[Vec3]) -> f32 {
+    others
+        .iter()
+        .map(|other| other.distance_squared(position))
+        .fold(f32::INFINITY, f32::min)
+}
+
+/// Spawn points are empties named `spawn...` or tagged with a truthy `"spawn"` extra.
+/// They face along the node's -Z axis, `transform` is the node's world transform.
+pub fn spawn_point_from_node(node: &GltfNode, transform: Transform) -> Option<SpawnPoint> {
+    let named = node.name.to_lowercase().starts_with("spawn");
+    let tagged = node_extras(node).get("spawn").is_some_and(|spawn| {
+        !matches!(
+            spawn,
+            serde_json::Value::Null | serde_json::Value::Bool(false)
+        )
+    });
+
+    if node.mesh.is_some() || !(named || tagged) {
+        return None;
+    }
+
+    let (yaw, pitch, _) = transform.rotation.to_euler(EulerRot::YXZ);
+    Some(SpawnPoint {
+        position: transform.translation,
+        yaw,
+        pitch,
+    })
+}
+
+/// Movement state [`reset_controller`] puts a player into.
+#[derive(Clone, Copy, Debug)]
+pub struct ControllerReset {
+    pub translation: Vec3,
+    pub velocity: Vec3,
+    pub height: f32,
+    pub ground_tick: u8,
+    pub has_traction: bool,
+}
+
+impl ControllerReset {
+    /// Standing still on `spawn_point`, as if just created.
+    pub fn spawn(spawn_point: &SpawnPoint, controller: &FpsController) -> Self {
+        Self {
+            translation: spawn_point.player_translation(controller.upright_height),
+            velocity: Vec3::ZERO,
+            height: controller.upright_height,
+            ground_tick: 0,
+            has_traction: false,
+        }
+    }
+}
+
+/// Replaces a player's simulated movement state, keeping the collider's height in step with
+/// the controller's. Respawns, replays and server corrections all go through here.
+pub fn reset_controller(
+    reset: ControllerReset,
+    controller: &mut FpsController,
+    collider: &mut Collider,
+    transform: &mut Transform,
+    velocity: &mut Velocity,
+) {
+    controller.height = reset.height;
+    controller.ground_tick = reset.ground_tick;
+    controller.has_traction = reset.has_traction;
+    set_collider_height(collider, reset.height);
+    transform.translation = reset.translation;
+    *velocity = Velocity::linear(reset.velocity);
+}
+
+#[allow(clippy::type_complexity)]
+pub fn respawn_player(
+    mut respawn_events: EventReader<RespawnPlayer>,
+    spawn_points: Res<SpawnPoints>,
+    spawn_policy: Res<SpawnPolicy>,
+    enemy_query: Query<&GlobalTransform, With<Enemy>>,
+    mut query: Query<
+        (
+            &mut FpsController,
+            &mut FpsControllerInput,
+            &mut Collider,
+            &mut Transform,
+            &mut Velocity,
+        ),
+        With<LogicalPlayer>,
+    >,
+) {
+    if respawn_events.is_empty() {
+        return;
+    }
+    respawn_events.clear();
+
+    let enemies: Vec<Vec3> = enemy_query.iter().map(|t| t.translation()).collect();
+    let spawn_point = spawn_points
+        .active
+        .unwrap_or_else(|| spawn_points.select(*spawn_policy, &enemies));
+
+    for (mut controller, mut input, mut collider, mut transform, mut velocity) in &mut query {
+        reset_controller(
+            ControllerReset::spawn(&spawn_point, &controller),
+            &mut controller,
+            &mut collider,
+            &mut transform,
+            &mut velocity,
+        );
+        controller.yaw = spawn_point.yaw;
+        controller.pitch = spawn_point.pitch;
+        *input = FpsControllerInput {
+            yaw: spawn_point.yaw,
+            pitch: spawn_point.pitch,
+            ..default()
+        };
+    }
+}
@@ -0,0 +1,236 @@
+use std::collections::VecDeque;
+
+use bevy::{color::palettes::css, math::Vec3Swizzles, prelude::*};
+use bevy_rapier3d::prelude::*;
+
+use crate::player::{FpsController, LogicalPlayer};
+
+/// Bars in the speed graph, one per sample.
+const GRAPH_COLUMNS: usize = 100;
+const GRAPH_COLUMN_WIDTH: f32 = 2.0;
+const GRAPH_HEIGHT: f32 = 80.0;
+
+pub struct SpeedometerPlugin;
+
+impl Plugin for SpeedometerPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Speedometer>()
+            .init_resource::<MovementHistory>()
+            .add_systems(Startup, spawn_speed_graph)
+            .add_systems(
+                Update,
+                (
+                    toggle_speedometer,
+                    record_movement,
+                    (update_speed_graph, draw_movement_trail),
+                )
+                    .chain(),
+            );
+    }
+}
+
+#[derive(Resource)]
+pub struct Speedometer {
+    /// Seconds of history shown by the graph and the trail
+    pub window: f32,
+    /// Horizontal speed at the top of the graph
+    pub max_speed: f32,
+    pub show_graph: bool,
+    pub show_trail: bool,
+    pub graph_key: KeyCode,
+    pub trail_key: KeyCode,
+}
+
+impl Default for Speedometer {
+    fn default() -> Self {
+        Self {
+            window: 5.0,
+            max_speed: 20.0,
+            show_graph: true,
+            show_trail: false,
+            graph_key: KeyCode::F7,
+            trail_key: KeyCode::F8,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MovementEvent {
+    Jump,
+    Land,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct MovementSample {
+    pub position: Vec3,
+    pub horizontal_speed: f32,
+    pub event: Option<MovementEvent>,
+}
+
+/// The player's last [`Speedometer::window`] seconds, sampled at a fixed rate.
+#[derive(Resource, Default)]
+pub struct MovementHistory {
+    pub samples: VecDeque<MovementSample>,
+    since_sample: f32,
+    was_grounded: bool,
+    /// Jump or landing that happened since the last sample
+    pending_event: Option<MovementEvent>,
+}
+
+#[derive(Component)]
+pub struct SpeedGraph;
+
+/// Bar showing the sample this many places from the oldest one on screen.
+#[derive(Component)]
+pub struct SpeedGraphColumn(pub usize);
+
+pub fn spawn_speed_graph(mut commands: Commands) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    bottom: Val::Px(5.0),
+                    left: Val::Px(5.0),
+                    width: Val::Px(GRAPH_COLUMNS as f32 * GRAPH_COLUMN_WIDTH),
+                    height: Val::Px(GRAPH_HEIGHT),
+                    align_items: AlignItems::FlexEnd,
+                    ..default()
+                },
+                background_color: Color::srgba(0.0, 0.0, 0.0, 0.4).into(),
+                ..default()
+            },
+            SpeedGraph,
+        ))
+        .with_children(|graph| {
+            for index in 0..GRAPH_COLUMNS {
+                graph.spawn((
+                    NodeBundle {
+                        style: Style {
+                            width: Val::Px(GRAPH_COLUMN_WIDTH),
+                            height: Val::Percent(0.0),
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    SpeedGraphColumn(index),
+                ));
+            }
+        });
+}
+
+pub fn toggle_speedometer(
+    key_input: Res<ButtonInput<KeyCode>>,
+    mut speedometer: ResMut<Speedometer>,
+) {
+    if key_input.just_pressed(speedometer.graph_key) {
+        speedometer.show_graph = !speedometer.show_graph;
+    }
+    if key_input.just_pressed(speedometer.trail_key) {
+        speedometer.show_trail = !speedometer.show_trail;
+    }
+}
+
+pub fn record_movement(
+    time: Res<Time>,
+    speedometer: Res<Speedometer>,
+    mut history: ResMut<MovementHistory>,
+    query: Query<(&FpsController, &Transform, &Velocity), With<LogicalPlayer>>,
+) {
+    let Ok((controller, transform, velocity)) = query.get_single() else {
+        return;
+    };
+
+    let grounded = controller.ground_tick >= 1;
+    if grounded != history.was_grounded {
+        history.was_grounded = grounded;
+        history.pending_event = Some(if grounded {
+            MovementEvent::Land
+        } else {
+            MovementEvent::Jump
+        });
+    }
+
+    let interval = speedometer.window / GRAPH_COLUMNS as f32;
+    history.since_sample += time.delta_seconds();
+    if history.since_sample < interval {
+        return;
+    }
+    history.since_sample %= interval;
+
+    let sample = MovementSample {
+        position: transform.translation,
+        horizontal_speed: velocity.linvel.xz().length(),
+        event: history.pending_event.take(),
+    };
+    history.samples.push_back(sample);
+    while history.samples.len() > GRAPH_COLUMNS {
+        history.samples.pop_front();
+    }
+}
+
+pub fn update_speed_graph(
+    speedometer: Res<Speedometer>,
+    history: Res<MovementHistory>,
+    mut graph_query: Query<&mut Visibility, With<SpeedGraph>>,
+    mut column_query: Query<(&SpeedGraphColumn, &mut Style, &mut BackgroundColor)>,
+) {
+    for mut visibility in &mut graph_query {
+        *visibility = if speedometer.show_graph {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+    }
+    if !speedometer.show_graph || !history.is_changed() {
+        return;
+    }
+
+    // Newest sample goes in the rightmost column
+    let missing = GRAPH_COLUMNS - history.samples.len();
+    for (column, mut style, mut background) in &mut column_query {
+        let Some(sample) = column
+            .0
+            .checked_sub(missing)
+            .and_then(|index| history.samples.get(index))
+        else {
+            style.height = Val::Percent(0.0);
+            continue;
+        };
+
+        let fraction = (sample.horizontal_speed / speedometer.max_speed).min(1.0);
+        style.height = Val::Percent(fraction * 100.0);
+        *background = match sample.event {
+            Some(MovementEvent::Jump) => css::YELLOW.into(),
+            Some(MovementEvent::Land) => css::AQUA.into(),
+            None => Color::srgba(1.0, 1.0, 1.0, 0.6).into(),
+        };
+    }
+}
+
+pub fn draw_movement_trail(
+    mut gizmos: Gizmos,
+    speedometer: Res<Speedometer>,
+    history: Res<MovementHistory>,
+) {
+    if !speedometer.show_trail {
+        return;
+    }
+
+    gizmos.linestrip_gradient(history.samples.iter().map(|sample| {
+        let fraction = (sample.horizontal_speed / speedometer.max_speed).min(1.0);
+        let color = Color::from(css::BLUE).mix(&css::RED.into(), fraction);
+        (sample.position, color)
+    }));
+    for sample in &history.samples {
+        match sample.event {
+            Some(MovementEvent::Jump) => {
+                gizmos.sphere(sample.position, Quat::IDENTITY, 0.1, css::YELLOW);
+            }
+            Some(MovementEvent::Land) => {
+                gizmos.sphere(sample.position, Quat::IDENTITY, 0.1, css::AQUA);
+            }
+            None => {}
+        }
+    }
+}
@@ -0,0 +1,230 @@
+use std::{fs, path::PathBuf};
+
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    level::MainScene,
+    trigger::{LevelCompleted, TriggerEnter, TriggerExit, TriggerKind, TriggerVolume},
+};
+
+pub struct SpeedrunPlugin;
+
+impl Plugin for SpeedrunPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SpeedrunTimer>()
+            .insert_resource(PersonalBests::load())
+            .add_systems(
+                Update,
+                (
+                    reset_run_on_level_change,
+                    start_run,
+                    tick_run,
+                    record_splits,
+                    finish_run,
+                )
+                    .chain(),
+            );
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RunState {
+    /// Waiting for the player to leave a start volume
+    #[default]
+    Idle,
+    Running,
+    Finished,
+}
+
+#[derive(Resource, Default)]
+pub struct SpeedrunTimer {
+    pub state: RunState,
+    /// Level the run belongs to
+    pub level: String,
+    /// Seconds since leaving the start volume
+    pub elapsed: f32,
+    /// Time at each checkpoint reached, in order
+    pub splits: Vec<f32>,
+    /// Personal best as it was when the run started
+    pub compared_to: Option<PersonalBest>,
+    reached: HashSet<Entity>,
+}
+
+impl SpeedrunTimer {
+    fn reset(&mut self, state: RunState) {
+        self.state = state;
+        self.elapsed = 0.0;
+        self.splits.clear();
+        self.reached.clear();
+    }
+
+    /// How far behind (positive) or ahead of [`Self::compared_to`] the run is at its
+    /// latest split or finish.
+    pub fn delta(&self) -> Option<f32> {
+        let best = self.compared_to.as_ref()?;
+        match self.state {
+            RunState::Finished => Some(self.elapsed - best.time),
+            RunState::Running => {
+                let split = self.splits.len().checked_sub(1)?;
+                Some(self.splits[split] - best.splits.get(split)?)
+            }
+            RunState::Idle => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PersonalBest {
+    pub time: f32,
+    pub splits: Vec<f32>,
+}
+
+/// Fastest finished run per level id, kept in the user's data directory.
+#[derive(Resource, Default, Serialize, Deserialize)]
+pub struct PersonalBests {
+    pub levels: HashMap<String, PersonalBest>,
+}
+
+impl PersonalBests {
+    fn path() -> Option<PathBuf> {
+        Some(dirs::data_dir()?.join("source").join("personal_bests.ron"))
+    }
+
+    /// Starts empty when there is no file yet or it can't be read.
+    pub fn load() -> Self {
+        let Some(contents) = Self::path().and_then(|path| fs::read_to_string(path).ok()) else {
+            return Self::default();
+        };
+        ron::from_str(&contents).unwrap_or_else(|error| {
+            warn!("Ignoring unreadable personal bests: {error}");
+            Self::default()
+        })
+    }
+
+    pub fn save(&self) {
+        let Some(path) = Self::path() else {
+            warn!("No data directory to save personal bests in");
+            return;
+        };
+        let result = ron::ser::to_string_pretty(self, default())
+            .map_err(|error| error.to_string())
+            .and_then(|contents| {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent).map_err(|error| error.to_string())?;
+                }
+                fs::write(&path, contents).map_err(|error| error.to_string())
+            });
+        if let Err(error) = result {
+            warn!(
+                "Couldn't save personal bests to {}: {error}",
+                path.display()
+            );
+        }
+    }
+}
+
+/// `m:ss.cc`
+pub fn format_run_time(seconds: f32) -> String {
+    let centiseconds = (seconds.max(0.0) * 100.0).round() as u32;
+    format!(
+        "{}:{:02}.{:02}",
+        centiseconds / 6000,
+        centiseconds / 100 % 60,
+        centiseconds % 100
+    )
+}
+
+pub fn reset_run_on_level_change(main_scene: Res<MainScene>, mut timer: ResMut<SpeedrunTimer>) {
+    if timer.level != main_scene.level {
+        timer.level.clone_from(&main_scene.level);
+        timer.reset(RunState::Idle);
+    }
+}
+
+pub fn start_run(
+    mut enter_events: EventReader<TriggerEnter>,
+    mut exit_events: EventReader<TriggerExit>,
+    trigger_query: Query<&TriggerVolume>,
+    personal_bests: Res<PersonalBests>,
+    mut timer: ResMut<SpeedrunTimer>,
+) {
+    let is_start = |trigger| {
+        matches!(
+            trigger_query.get(trigger),
+            Ok(TriggerVolume {
+                kind: TriggerKind::RunStart
+            })
+        )
+    };
+
+    // Going back into the start volume abandons the run
+    let entered = enter_events.read().filter(|event| is_start(event.trigger));
+    if entered.count() > 0 {
+        timer.reset(RunState::Idle);
+    }
+    let left = exit_events.read().filter(|event| is_start(event.trigger));
+    if left.count() > 0 {
+        timer.reset(RunState::Running);
+        timer.compared_to = personal_bests.levels.get(&timer.level).cloned();
+    }
+}
+
+pub fn tick_run(time: Res<Time>, mut timer: ResMut<SpeedrunTimer>) {
+    if timer.state == RunState::Running {
+        timer.elapsed += time.delta_seconds();
+    }
+}
+
+pub fn record_splits(
+    mut enter_events: EventReader<TriggerEnter>,
+    trigger_query: Query<&TriggerVolume>,
+    mut timer: ResMut<SpeedrunTimer>,
+) {
+    for event in enter_events.read() {
+        let Ok(TriggerVolume {
+            kind: TriggerKind::Checkpoint(_),
+        }) = trigger_query.get(event.trigger)
+        else {
+            continue;
+        };
+        if timer.state == RunState::Running && timer.reached.insert(event.trigger) {
+            let elapsed = timer.elapsed;
+            timer.splits.push(elapsed);
+        }
+    }
+}
+
+pub fn finish_run(
+    mut level_completed_events: EventReader<LevelCompleted>,
+    mut timer: ResMut<SpeedrunTimer>,
+    mut personal_bests: ResMut<PersonalBests>,
+) {
+    if level_completed_events.read().count() == 0 || timer.state != RunState::Running {
+        return;
+    }
+    timer.state = RunState::Finished;
+    info!(
+        "Finished {} in {}",
+        timer.level,
+        format_run_time(timer.elapsed)
+    );
+
+    let is_best = match personal_bests.levels.get(&timer.level) {
+        Some(best) => timer.elapsed < best.time,
+        None => true,
+    };
+    if is_best {
+        personal_bests.levels.insert(
+            timer.level.clone(),
+            PersonalBest {
+                time: timer.elapsed,
+                splits: timer.splits.clone(),
+            },
+        );
+        personal_bests.save();
+    }
+}
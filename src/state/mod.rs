@@ -0,0 +1,155 @@
+use bevy::{prelude::*, window::CursorGrabMode};
+use bevy_rapier3d::prelude::*;
+
+use crate::{
+    health::Dead,
+    level::{scene_colliders, MainScene, SceneStatus},
+    net::{NetClient, NetServer},
+    player::{FpsController, FpsControllerInput, LogicalPlayer},
+};
+
+/// Boots into [`GameState::Loading`] once the first level is requested and follows the
+/// [`MainScene`] from there, so the player and physics only run on a level whose colliders
+/// exist. Escape pauses and resumes, pausing offline stops [`Time<Virtual>`], which stops the
+/// fixed timestep and with it physics and player movement. Leaving the menu for a new map only
+/// resumes once that map is in game.
+pub struct GameStatePlugin;
+
+impl Plugin for GameStatePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_state::<GameState>()
+            .add_systems(
+                Update,
+                (follow_scene_status.after(scene_colliders), toggle_pause),
+            )
+            .add_systems(OnEnter(GameState::Boot), set_physics_active(false))
+            .add_systems(OnEnter(GameState::Loading), set_physics_active(false))
+            .add_systems(OnEnter(GameState::InGame), set_physics_active(true))
+            .add_systems(OnEnter(GameState::Paused), pause)
+            .add_systems(
+                OnEnter(GameState::InGame),
+                resume.run_if(resource_exists::<MenuPause>),
+            );
+    }
+}
+
+#[derive(States, Default, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GameState {
+    /// Before any level has been asked for
+    #[default]
+    Boot,
+    /// The level is loading or hot reloading
+    Loading,
+    InGame,
+    Paused,
+}
+
+/// Whether the level is in place for the player and physics to run on.
+pub fn scene_ready(state: Option<Res<State<GameState>>>) -> bool {
+    matches!(
+        state.as_deref().map(State::get),
+        Some(GameState::InGame | GameState::Paused)
+    )
+}
+
+pub fn follow_scene_status(
+    main_scene: Res<MainScene>,
+    state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if !main_scene.is_changed() {
+        return;
+    }
+    let next = match (main_scene.status, state.get()) {
+        (SceneStatus::Loading, GameState::Loading) => return,
+        (SceneStatus::Loading, _) => GameState::Loading,
+        (SceneStatus::Loaded, GameState::Loading) => GameState::InGame,
+        // Nothing to play on, the pause menu has the map select
+        (SceneStatus::Failed, GameState::Loading) => GameState::Paused,
+        _ => return,
+    };
+    next_state.set(next);
+}
+
+pub fn toggle_pause(
+    key_input: Res<ButtonInput<KeyCode>>,
+    main_scene: Res<MainScene>,
+    state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if !key_input.just_pressed(KeyCode::Escape) {
+        return;
+    }
+    match state.get() {
+        GameState::InGame => next_state.set(GameState::Paused),
+        GameState::Paused if can_resume(&main_scene) => next_state.set(GameState::InGame),
+        GameState::Paused | GameState::Boot | GameState::Loading => {}
+    }
+}
+
+/// A level that failed to load has nothing to go back to, only the map select.
+pub fn can_resume(main_scene: &MainScene) -> bool {
+    main_scene.status != SceneStatus::Failed
+}
+
+/// Physics only steps once the level's colliders are in place.
+pub fn set_physics_active(active: bool) -> impl FnMut(ResMut<RapierConfiguration>) {
+    move |mut rapier_configuration| rapier_configuration.physics_pipeline_active = active
+}
+
+/// Left by [`pause`] until the game is back in [`GameState::InGame`], possibly through
+/// [`GameState::Loading`] when a map was picked from the menu.
+#[derive(Resource)]
+pub struct MenuPause {
+    /// Whether pausing stopped [`Time<Virtual>`], a replay paused with P stays paused
+    stopped_time: bool,
+}
+
+pub fn pause(
+    mut commands: Commands,
+    menu_pause: Option<Res<MenuPause>>,
+    mut time: ResMut<Time<Virtual>>,
+    server: Option<Res<NetServer>>,
+    client: Option<Res<NetClient>>,
+    mut window_query: Query<&mut Window>,
+    mut player_query: Query<(&mut FpsController, &mut FpsControllerInput), With<LogicalPlayer>>,
+) {
+    // Everyone else keeps playing online
+    let mut stopped_time = menu_pause.is_some_and(|menu_pause| menu_pause.stopped_time);
+    if server.is_none() && client.is_none() && !time.is_paused() {
+        time.pause();
+        stopped_time = true;
+    }
+    commands.insert_resource(MenuPause { stopped_time });
+    for mut window in &mut window_query {
+        window.cursor.grab_mode = CursorGrabMode::None;
+        window.cursor.visible = true;
+    }
+    for (mut controller, mut input) in &mut player_query {
+        controller.enable_input = false;
+        // Keys let go of while paused never reach the controller
+        input.movement = Vec3::ZERO;
+        input.jump = false;
+        input.crouch = false;
+    }
+}
+
+pub fn resume(
+    mut commands: Commands,
+    menu_pause: Res<MenuPause>,
+    mut time: ResMut<Time<Virtual>>,
+    mut window_query: Query<&mut Window>,
+    mut player_query: Query<&mut FpsController, (With<LogicalPlayer>, Without<Dead>)>,
+) {
+    commands.remove_resource::<MenuPause>();
+    if menu_pause.stopped_time {
+        time.unpause();
+    }
+    for mut window in &mut window_query {
+        window.cursor.grab_mode = CursorGrabMode::Locked;
+        window.cursor.visible = false;
+    }
+    for mut controller in &mut player_query {
+        controller.enable_input = true;
+    }
+}
@@ -0,0 +1,223 @@
+use bevy::{gltf::GltfNode, prelude::*};
+use bevy_rapier3d::prelude::*;
+
+use crate::{
+    level::node_extras,
+    player::{FpsController, FpsControllerInput, LogicalPlayer},
+    spawn::{SpawnPoint, SpawnPoints},
+};
+
+/// Center and half extents of an empty drawn as a cube.
+const EMPTY_BOUNDS: (Vec3, Vec3) = (Vec3::ZERO, Vec3::ONE);
+
+pub struct TriggerPlugin;
+
+impl Plugin for TriggerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<TriggerEnter>()
+            .add_event::<TriggerExit>()
+            .add_event::<LevelCompleted>()
+            .add_systems(
+                Update,
+                (
+                    trigger_events,
+                    (checkpoint_triggers, teleporter_triggers, level_end_triggers),
+                )
+                    .chain(),
+            );
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum TriggerKind {
+    /// Only emits [`TriggerEnter`]/[`TriggerExit`]
+    Plain,
+    /// Makes its spawn point the active one
+    Checkpoint(SpawnPoint),
+    /// Moves the player to its destination
+    Teleporter(SpawnPoint),
+    /// Starts the speedrun timer when left
+    RunStart,
+    LevelEnd,
+}
+
+#[derive(Component)]
+pub struct TriggerVolume {
+    pub kind: TriggerKind,
+}
+
+#[derive(Event)]
+pub struct TriggerEnter {
+    pub trigger: Entity,
+    pub player: Entity,
+}
+
+#[derive(Event)]
+pub struct TriggerExit {
+    pub trigger: Entity,
+    pub player: Entity,
+}
+
+#[derive(Event)]
+pub struct LevelCompleted;
+
+/// Trigger volumes are nodes named `trigger...`, `checkpoint...`, `teleport...`, `start...` or
+/// `finish...`, or tagged with a `"trigger"` extra holding one of those words.
+///
+/// Checkpoints respawn the player at the bottom of their volume, teleporters land on the node
+/// named by their `"target"` extra, or `<name>_target`.
+/// `transform` is the node's world transform and `find_node` looks up the world transform of
+/// another node in the same glTF by name.
+pub fn trigger_kind_from_node(
+    node: &GltfNode,
+    transform: Transform,
+    bounds: Option<(Vec3, Vec3)>,
+    find_node: impl Fn(&str) -> Option<Transform>,
+) -> Option<TriggerKind> {
+    let extras = node_extras(node);
+    let name = node.name.to_lowercase();
+    if name.ends_with("_target") {
+        return None;
+    }
+
+    let tag = match extras.get("trigger").and_then(|tag| tag.as_str()) {
+        Some(tag) => tag.to_lowercase(),
+        None => ["trigger", "checkpoint", "teleport", "start", "finish"]
+            .into_iter()
+            .find(|prefix| name.starts_with(prefix))?
+            .to_string(),
+    };
+
+    let (center, half_extents) = bounds.unwrap_or(EMPTY_BOUNDS);
+    let kind = match tag.as_str() {
+        "checkpoint" => TriggerKind::Checkpoint(SpawnPoint {
+            position: transform.transform_point(center - Vec3::Y * half_extents.y),
+            yaw: transform.rotation.to_euler(EulerRot::YXZ).0,
+            pitch: 0.0,
+        }),
+        "teleport" => {
+            let target = match extras.get("target").and_then(|target| target.as_str()) {
+                Some(target) => target.to_string(),
+                None => format!("{}_target", node.name),
+            };
+            let Some(target) = find_node(&target) else {
+                warn!("Teleporter {} has no target node {target}", node.name);
+                return None;
+            };
+            let (yaw, pitch, _) = target.rotation.to_euler(EulerRot::YXZ);
+            TriggerKind::Teleporter(SpawnPoint {
+                position: target.translation,
+                yaw,
+                pitch,
+            })
+        }
+        "start" => TriggerKind::RunStart,
+        "finish" => TriggerKind::LevelEnd,
+        _ => TriggerKind::Plain,
+    };
+    Some(kind)
+}
+
+/// A sensor filling the given local bounds, an empty's unit cube when there is no mesh.
+pub fn trigger_volume_bundle(
+    kind: TriggerKind,
+    transform: Transform,
+    bounds: Option<(Vec3, Vec3)>,
+) -> impl Bundle {
+    let (center, half_extents) = bounds.unwrap_or(EMPTY_BOUNDS);
+    (
+        Collider::cuboid(half_extents.x, half_extents.y, half_extents.z),
+        Sensor,
+        ActiveEvents::COLLISION_EVENTS,
+        TransformBundle::from_transform(transform * Transform::from_translation(center)),
+        TriggerVolume { kind },
+    )
+}
+
+pub fn trigger_events(
+    mut collision_events: EventReader<CollisionEvent>,
+    trigger_query: Query<(), With<TriggerVolume>>,
+    player_query: Query<(), With<LogicalPlayer>>,
+    mut enter_events: EventWriter<TriggerEnter>,
+    mut exit_events: EventWriter<TriggerExit>,
+) {
+    for event in collision_events.read() {
+        let (a, b, started) = match *event {
+            CollisionEvent::Started(a, b, _) => (a, b, true),
+            CollisionEvent::Stopped(a, b, _) => (a, b, false),
+        };
+        let (trigger, player) = if trigger_query.contains(a) && player_query.contains(b) {
+            (a, b)
+        } else if trigger_query.contains(b) && player_query.contains(a) {
+            (b, a)
+        } else {
+            continue;
+        };
+
+        if started {
+            enter_events.send(TriggerEnter { trigger, player });
+        } else {
+            exit_events.send(TriggerExit { trigger, player });
+        }
+    }
+}
+
+pub fn checkpoint_triggers(
+    mut enter_events: EventReader<TriggerEnter>,
+    trigger_query: Query<&TriggerVolume>,
+    mut spawn_points: ResMut<SpawnPoints>,
+) {
+    for event in enter_events.read() {
+        if let Ok(TriggerVolume {
+            kind: TriggerKind::Checkpoint(spawn_point),
+        }) = trigger_query.get(event.trigger)
+        {
+            spawn_points.active = Some(*spawn_point);
+        }
+    }
+}
+
+pub fn teleporter_triggers(
+    mut enter_events: EventReader<TriggerEnter>,
+    trigger_query: Query<&TriggerVolume>,
+    mut player_query: Query<(
+        &FpsController,
+        &mut FpsControllerInput,
+        &mut Transform,
+        &mut Velocity,
+    )>,
+) {
+    for event in enter_events.read() {
+        let Ok(TriggerVolume {
+            kind: TriggerKind::Teleporter(destination),
+        }) = trigger_query.get(event.trigger)
+        else {
+            continue;
+        };
+        let Ok((controller, mut input, mut transform, mut velocity)) =
+            player_query.get_mut(event.player)
+        else {
+            continue;
+        };
+
+        transform.translation = destination.player_translation(controller.height);
+        input.yaw = destination.yaw;
+        input.pitch = destination.pitch;
+        velocity.linvel = Vec3::ZERO;
+    }
+}
+
+pub fn level_end_triggers(
+    mut enter_events: EventReader<TriggerEnter>,
+    trigger_query: Query<&TriggerVolume>,
+    mut level_completed_events: EventWriter<LevelCompleted>,
+) {
+    for event in enter_events.read() {
+        if let Ok(TriggerVolume {
+            kind: TriggerKind::LevelEnd,
+        }) = trigger_query.get(event.trigger)
+        {
+            level_completed_events.send(LevelCompleted);
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use std::f32::consts::TAU;
+
+use bevy::{math::Vec3Swizzles, prelude::*, render::view::RenderLayers};
+use bevy_rapier3d::prelude::*;
+
+use crate::player::{FpsController, FpsControllerInput, LogicalPlayer};
+
+/// Render layer used by the viewmodel camera and everything it draws.
+/// The world camera only renders layer 0, so held items never show up (or clip) in the world.
+pub const VIEW_MODEL_RENDER_LAYER: usize = 1;
+
+pub struct ViewModelPlugin;
+
+impl Plugin for ViewModelPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, view_model_motion);
+    }
+}
+
+/// Marker for the camera that draws the held weapon on top of the world.
+#[derive(Component)]
+pub struct ViewModelCamera;
+
+#[derive(Component)]
+pub struct ViewModel {
+    /// Resting position relative to the viewmodel camera
+    pub rest_offset: Vec3,
+    /// How far the model lags behind per radian of mouse motion
+    pub sway_amount: f32,
+    pub max_sway: f32,
+    pub sway_smoothing: f32,
+    /// Bob amplitude at walk speed
+    pub bob_amount: f32,
+    /// Bob cycles per second at walk speed
+    pub bob_frequency: f32,
+    pub sway: Vec2,
+    pub bob_phase: f32,
+    pub bob_weight: f32,
+}
+
+impl Default for ViewModel {
+    fn default() -> Self {
+        Self {
+            rest_offset: Vec3::new(0.3, -0.25, -0.6),
+            sway_amount: 1.5,
+            max_sway: 0.08,
+            sway_smoothing: 12.0,
+            bob_amount: 0.03,
+            bob_frequency: 1.6,
+            sway: Vec2::ZERO,
+            bob_phase: 0.0,
+            bob_weight: 0.0,
+        }
+    }
+}
+
+pub fn view_model_render_layers() -> RenderLayers {
+    RenderLayers::layer(VIEW_MODEL_RENDER_LAYER)
+}
+
+pub fn view_model_motion(
+    time: Res<Time>,
+    logical_query: Query<(&FpsController, &FpsControllerInput, &Velocity), With<LogicalPlayer>>,
+    mut view_model_query: Query<(&mut Transform, &mut ViewModel)>,
+) {
+    let Ok((controller, input, velocity)) = logical_query.get_single() else {
+        return;
+    };
+    let dt = time.delta_seconds();
+
+    for (mut transform, mut view_model) in view_model_query.iter_mut() {
+        /* Sway */
+
+        let look_delta = if controller.enable_input {
+            input.look_delta
+        } else {
+            Vec2::ZERO
+        };
+        let max_sway = view_model.max_sway;
+        let target_sway = (-look_delta * view_model.sway_amount).clamp_length_max(max_sway);
+        let smoothing = 1.0 - f32::exp(-view_model.sway_smoothing * dt);
+        view_model.sway = view_model.sway.lerp(target_sway, smoothing);
+
+        /* Bob */
+
+        let speed_ratio = if controller.ground_tick >= 1 {
+            (velocity.linvel.xz().length() / controller.walk_speed).min(1.0)
+        } else {
+            0.0
+        };
+        view_model.bob_weight = view_model.bob_weight.lerp(speed_ratio, smoothing);
+        view_model.bob_phase =
+            (view_model.bob_phase + dt * view_model.bob_frequency * speed_ratio * TAU) % TAU;
+
+        let bob_amount = view_model.bob_amount * view_model.bob_weight;
+        let bob = Vec3::new(
+            f32::sin(view_model.bob_phase) * bob_amount,
+            -f32::abs(f32::cos(view_model.bob_phase)) * bob_amount,
+            0.0,
+        );
+
+        transform.translation =
+            view_model.rest_offset + bob + Vec3::new(view_model.sway.x, -view_model.sway.y, 0.0);
+        transform.rotation = Quat::from_euler(
+            EulerRot::YXZ,
+            view_model.sway.x * 2.0,
+            view_model.sway.y * 2.0,
+            view_model.sway.x,
+        );
+    }
+}
@@ -0,0 +1,153 @@
+//! A client and a server app talking over loopback, with the client's packets going through a
+//! [`LinkConditioner`].
+
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::*;
+use source::net::{LinkConditioner, Transport};
+
+const PACKETS: u32 = 200;
+/// How long to keep polling after the last send, longer than any delay used here.
+const DRAIN_TIME: Duration = Duration::from_millis(300);
+
+#[derive(Resource)]
+struct Client {
+    transport: Transport,
+    server: SocketAddr,
+    next: u32,
+}
+
+#[derive(Resource)]
+struct Server {
+    transport: Transport,
+    received: Vec<u32>,
+    first_arrival: Option<Instant>,
+}
+
+/// Sends one numbered packet per update, and keeps polling so held back ones go out.
+fn client_send(mut client: ResMut<Client>) {
+    let client = &mut *client;
+    if client.next < PACKETS {
+        client.transport.send(&client.next, client.server);
+        client.next += 1;
+    }
+    while client.transport.receive::<u32>().is_some() {}
+}
+
+fn server_receive(mut server: ResMut<Server>) {
+    while let Some((packet, _)) = server.transport.receive::<u32>() {
+        server.first_arrival.get_or_insert_with(Instant::now);
+        server.received.push(packet);
+    }
+}
+
+struct Run {
+    received: Vec<u32>,
+    first_delay: Option<Duration>,
+}
+
+fn run(conditioner: LinkConditioner) -> Run {
+    let server_transport = Transport::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let server_address = server_transport.local_addr().unwrap();
+    let mut server = App::new();
+    server
+        .add_plugins(MinimalPlugins)
+        .insert_resource(Server {
+            transport: server_transport,
+            received: Vec::new(),
+            first_arrival: None,
+        })
+        .add_systems(Update, server_receive);
+
+    let mut client_transport = Transport::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    client_transport.set_conditioner(conditioner);
+    let mut client = App::new();
+    client
+        .add_plugins(MinimalPlugins)
+        .insert_resource(Client {
+            transport: client_transport,
+            server: server_address,
+            next: 0,
+        })
+        .add_systems(Update, client_send);
+
+    let start = Instant::now();
+    let mut last_send: Option<Instant> = None;
+    loop {
+        client.update();
+        server.update();
+        match last_send {
+            Some(last) if last.elapsed() >= DRAIN_TIME => break,
+            None if client.world().resource::<Client>().next == PACKETS => {
+                last_send = Some(Instant::now());
+            }
+            _ => {}
+        }
+        std::thread::sleep(Duration::from_micros(500));
+    }
+
+    let server = server.world_mut().resource_mut::<Server>();
+    Run {
+        received: server.received.clone(),
+        first_delay: server.first_arrival.map(|arrival| arrival - start),
+    }
+}
+
+fn sorted(packets: &[u32]) -> Vec<u32> {
+    let mut packets = packets.to_vec();
+    packets.sort_unstable();
+    packets
+}
+
+#[test]
+fn delivers_every_packet_in_order_after_the_latency() {
+    let latency = 0.05;
+    let run = run(LinkConditioner {
+        latency,
+        ..default()
+    });
+
+    assert_eq!(run.received, (0..PACKETS).collect::<Vec<_>>());
+    assert!(run.first_delay.unwrap() >= Duration::from_secs_f32(latency));
+}
+
+#[test]
+fn drops_lost_packets() {
+    let run = run(LinkConditioner {
+        loss: 0.5,
+        ..default()
+    });
+
+    let received = run.received.len() as u32;
+    assert!(
+        (PACKETS / 4..PACKETS * 3 / 4).contains(&received),
+        "{received} of {PACKETS} arrived"
+    );
+    // Nothing duplicated or reordered on top
+    assert!(run.received.windows(2).all(|pair| pair[0] < pair[1]));
+}
+
+#[test]
+fn delivers_duplicated_packets_twice() {
+    let run = run(LinkConditioner {
+        duplication: 1.0,
+        ..default()
+    });
+
+    let expected: Vec<u32> = (0..PACKETS).flat_map(|packet| [packet, packet]).collect();
+    assert_eq!(sorted(&run.received), expected);
+}
+
+#[test]
+fn lets_later_packets_overtake_reordered_ones() {
+    let run = run(LinkConditioner {
+        reordering: 0.5,
+        ..default()
+    });
+
+    assert_eq!(sorted(&run.received), (0..PACKETS).collect::<Vec<_>>());
+    assert_ne!(run.received, sorted(&run.received), "nothing was reordered");
+}